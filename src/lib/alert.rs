@@ -0,0 +1,289 @@
+//! Alert rules, evaluated by helpers through which a slot's stderr and
+//! output pass, on their way to the err file and the FIFO, so that each
+//! line is seen once, as it is written, without following the file.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    cfg::{Alert, ValueAlert},
+    notify,
+};
+
+const FILE_NAME: &str = "alerts.toml";
+
+/// Of a slot.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct Rules {
+    /// For stderr.
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+
+    /// For output.
+    #[serde(default)]
+    pub value_alerts: Vec<ValueAlert>,
+}
+
+/// Path of the slot's rules, in its dir.
+pub fn path(slot_dir: &Path) -> PathBuf {
+    slot_dir.join(FILE_NAME)
+}
+
+pub fn write(slot_dir: &Path, rules: &Rules) -> Result<()> {
+    let path = path(slot_dir);
+    let data = toml::to_string(rules)?;
+    std::fs::write(&path, data).with_context(|| {
+        format!("Failed to write alert rules to: {:?}", &path)
+    })
+}
+
+fn read(path: &Path) -> Result<Rules> {
+    let data = crate::fs::read_to_string(path)?;
+    let rules = toml::from_str(&data)
+        .with_context(|| format!("Invalid alert rules in: {:?}", path))?;
+    Ok(rules)
+}
+
+/// Of the stderr of a slot.
+pub struct ErrAlerts<'a> {
+    settings: &'a Path,
+    slot: &'a str,
+    rules: Vec<(Alert, regex::Regex, Option<Instant>)>,
+    ansi: regex::Regex,
+}
+
+impl<'a> ErrAlerts<'a> {
+    pub fn new(
+        settings: &'a Path,
+        slot: &'a str,
+        rules: &Path,
+    ) -> Result<Self> {
+        let rules = read(rules)?
+            .alerts
+            .into_iter()
+            .map(|alert| {
+                let regex =
+                    regex::Regex::new(&alert.pattern).with_context(|| {
+                        format!("Invalid alert pattern: {:?}", &alert.pattern)
+                    })?;
+                Ok((alert, regex, None))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            settings,
+            slot,
+            rules,
+            ansi: regex::Regex::new(r"\x1b\[[0-9;]*m")?,
+        })
+    }
+
+    /// Notifies of the line, if it matches any of the rules.
+    pub fn check(&mut self, line: &str) {
+        let (line, names) = self.alerted(line, Instant::now());
+        for name in names {
+            send(self.settings, self.slot, &name, &format!("line: {}", line));
+        }
+    }
+
+    /// Names of the rules alerted of the line, at the time, outside their
+    /// cooldown, and the line, without its terminal color codes.
+    fn alerted<'l>(
+        &mut self,
+        line: &'l str,
+        now: Instant,
+    ) -> (std::borrow::Cow<'l, str>, Vec<String>) {
+        let line = self.ansi.replace_all(line, "");
+        let mut names = vec![];
+        for (alert, regex, last) in self.rules.iter_mut() {
+            if !regex.is_match(&line) {
+                continue;
+            }
+            let cooldown = Duration::from_secs(alert.cooldown);
+            if last.is_some_and(|last| now.duration_since(last) < cooldown) {
+                tracing::debug!("Alert in cooldown: {:?}", alert.name());
+                continue;
+            }
+            *last = Some(now);
+            names.push(alert.name().to_string());
+        }
+        (line, names)
+    }
+}
+
+/// Copies stdin to the FIFO, notifying of the numbers in the lines which
+/// cross the thresholds of the rules.
+pub fn watch_out(
+    settings: &Path,
+    slot: &str,
+    rules: &Path,
+    fifo: &Path,
+) -> Result<()> {
+    let mut rules: Vec<(ValueAlert, regex::Regex, Threshold)> = read(rules)?
+        .value_alerts
+        .into_iter()
+        .map(|alert| {
+            let regex = alert.regex().with_context(|| {
+                format!("Invalid value alert: {:?}", alert.name())
+            })?;
+            Ok((alert, regex, Threshold::default()))
+        })
+        .collect::<Result<_>>()?;
+    // Opened also for reading, for the same reason the slot's cmd opens
+    // it so: to outlive restarts of pista, instead of getting SIGPIPE.
+    let mut out = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(fifo)
+        .with_context(|| format!("Failed to open: {:?}", fifo))?;
+    crate::fs::pass_lines(&mut out, |line| {
+        for (alert, regex, threshold) in rules.iter_mut() {
+            let Some(capture) = regex.captures(line).and_then(|c| c.get(1))
+            else {
+                continue;
+            };
+            let value: f64 = match capture.as_str().trim().parse() {
+                Ok(value) => value,
+                Err(_) => {
+                    tracing::debug!(
+                        "Not a number: {:?}, in: {:?}",
+                        capture.as_str(),
+                        line
+                    );
+                    continue;
+                }
+            };
+            if threshold.update(alert, value) {
+                let details = match (alert.above, alert.below) {
+                    (Some(above), _) => format!("{} > {}", value, above),
+                    (_, Some(below)) => format!("{} < {}", value, below),
+                    (None, None) => value.to_string(),
+                };
+                send(
+                    settings,
+                    slot,
+                    alert.name(),
+                    &format!("value: {}\nline: {}", details, line),
+                );
+            }
+        }
+    })
+}
+
+/// Whether a rule is in alert, which it leaves only once the value gets
+/// back past the threshold by the hysteresis.
+#[derive(Debug, Default)]
+struct Threshold {
+    alerted: bool,
+}
+
+impl Threshold {
+    /// Whether to alert of the value.
+    fn update(&mut self, alert: &ValueAlert, value: f64) -> bool {
+        let (crossed, cleared) = match (alert.above, alert.below) {
+            (Some(above), _) => {
+                (value > above, value <= above - alert.hysteresis)
+            }
+            (_, Some(below)) => {
+                (value < below, value >= below + alert.hysteresis)
+            }
+            (None, None) => (false, true),
+        };
+        if self.alerted {
+            if cleared {
+                self.alerted = false;
+            }
+            false
+        } else {
+            self.alerted = crossed;
+            crossed
+        }
+    }
+}
+
+fn send(settings: &Path, slot: &str, rule: &str, details: &str) {
+    let body = format!("slot: {}\nrule: {}\n{}", slot, rule, details);
+    let result = notify::send(
+        settings,
+        notify::Event::Alert,
+        Some(slot.to_string()),
+        None,
+        body,
+        None,
+    );
+    if let Err(err) = result {
+        tracing::error!(
+            "Failed to notify of alert: {:?}. Error: {:?}",
+            rule,
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    use super::{ErrAlerts, Threshold};
+
+    #[test]
+    fn err_alerts() {
+        let alert = |name: Option<&str>, pattern: &str, cooldown| {
+            let alert = crate::cfg::Alert {
+                name: name.map(str::to_string),
+                pattern: pattern.to_string(),
+                cooldown,
+            };
+            let regex = regex::Regex::new(pattern).unwrap();
+            (alert, regex, None)
+        };
+        let mut alerts = ErrAlerts {
+            settings: Path::new(""),
+            slot: "a",
+            rules: vec![
+                alert(Some("error"), "^ERROR: ", 60),
+                alert(None, "timeout", 0),
+            ],
+            ansi: regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap(),
+        };
+        let start = Instant::now();
+        let mut check = |line, secs| {
+            let (line, names) =
+                alerts.alerted(line, start + Duration::from_secs(secs));
+            (line.into_owned(), names)
+        };
+        let (line, names) = check("\x1b[1;31mERROR:\x1b[0m timeout", 0);
+        assert_eq!("ERROR: timeout", line);
+        assert_eq!(vec!["error", "timeout"], names);
+        assert!(check("ERROR: x", 59).1.is_empty());
+        assert_eq!(vec!["timeout"], check("ERROR: timeout", 59).1);
+        assert_eq!(vec!["error"], check("ERROR: x", 60).1);
+        assert!(check("INFO: ERROR: x", 200).1.is_empty());
+    }
+
+    #[test]
+    fn threshold() {
+        let alert = crate::cfg::ValueAlert {
+            name: None,
+            pattern: String::new(),
+            above: None,
+            below: Some(10.0),
+            hysteresis: 5.0,
+        };
+        let mut threshold = Threshold::default();
+        let alerts: Vec<bool> = [50.0, 9.0, 8.0, 11.0, 9.0, 15.0, 20.0, 9.5]
+            .into_iter()
+            .map(|value| threshold.update(&alert, value))
+            .collect();
+        assert_eq!(
+            vec![false, true, false, false, false, false, false, true],
+            alerts
+        );
+    }
+}
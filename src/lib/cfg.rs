@@ -1,20 +1,178 @@
+pub mod edit;
+pub mod migrate;
+pub mod overrides;
+pub mod paths;
+
+mod load;
+mod profile;
+mod templates;
+mod vars;
+mod when;
+
+pub use when::When;
+
 mod file {
-    #[derive(Debug, serde::Deserialize)]
+    /// Config of pistactl, the pista session controller.
+    #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
     pub struct Cfg {
+        /// Of the format the file is written in. Default: 1
+        pub version: Option<i64>,
+
+        /// Paths, or glob patterns, of config files to merge on top.
+        pub include: Option<Vec<String>>,
+
+        /// Increase logging verbosity.
         pub debug: Option<bool>,
+
+        /// Name of tmux sock. Corresponds to tmux -L
         pub sock_name: Option<String>,
+
+        /// Name of tmux session.
         pub session: Option<String>,
+
+        /// Directory where slot subdirectories with FIFOs will be created.
         pub slots_fifos_dir: Option<String>,
-        pub notifications: Option<super::Notifications>,
+
+        pub notifications: Option<Notifications>,
+        pub logs: Option<Logs>,
         pub pista: Option<super::Pista>,
+
+        /// Values for {name} placeholders in slot cmd and env.
+        pub vars: Option<std::collections::BTreeMap<String, String>>,
+
+        /// Name of the profile to use, when none is given on command line.
+        pub profile: Option<String>,
+
+        /// Named variations of the config.
+        pub profiles: Option<
+            std::collections::BTreeMap<String, super::profile::Profile>,
+        >,
+
+        /// Fields of all slots, unless set by the slot or its template.
+        // Checked by templates::validate. Resolved into the slots before
+        // deserialization.
+        pub slot_defaults: Option<super::templates::PartialSlot>,
+
+        /// Named sets of slot fields, which slots refer to with extends.
+        pub slot_templates: Option<
+            std::collections::BTreeMap<String, super::templates::PartialSlot>,
+        >,
+    }
+
+    /// Of the slots' err, and pista's out and err, files. Rotation is off,
+    /// unless max_size or max_age is set.
+    #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Logs {
+        /// Bytes, beyond which a file is rotated.
+        pub max_size: Option<u64>,
+
+        /// Seconds since a file was started, after which it is rotated.
+        pub max_age: Option<u64>,
+
+        /// Rotated files kept, gzip-compressed. Default: 5
+        pub keep: Option<usize>,
+
+        /// Prefix each line of the slots' err with the time it was written,
+        /// in the format of pistactl's own log, so that logs --since and
+        /// --until can filter them. Default: false
+        pub timestamps: Option<bool>,
+    }
+
+    /// Of the notifications sent when a slot or pista exits.
+    /// Missing fields are taken from the defaults.
+    #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+    pub struct Notifications {
+        /// Number of last lines of the stderr log to include. Default: 10
+        pub log_lines_limit: Option<usize>,
+
+        /// Characters after which the log lines are cut. Default: 150
+        pub width_limit: Option<usize>,
+
+        /// Prepended to each of the log lines. Default: 4 spaces
+        pub indent: Option<String>,
+
+        /// Seconds over which the rate limits count notifications.
+        /// Default: 300
+        pub rate_limit_window: Option<u64>,
+
+        /// Notifications per slot, or pista, within the window, after which
+        /// they are dropped, and summarized in the next one let through.
+        /// Default: unlimited
+        pub slot_rate_limit: Option<usize>,
+
+        /// Notifications within the window, of all slots together.
+        /// Default: unlimited
+        pub global_rate_limit: Option<usize>,
+
+        /// Seconds within which a notification identical to the previous one
+        /// of the same slot and event is dropped. Default: 0, i.e. none are.
+        pub coalesce_window: Option<u64>,
+
+        /// How notifications are sent. Default: dbus
+        pub backend: Option<super::Backend>,
+
+        /// Shell command for the command backend,
+        /// which receives the notification in JSON on stdin.
+        pub command: Option<String>,
+
+        /// For the file backend, to append notifications to,
+        /// in JSON, one per line.
+        pub file: Option<String>,
+
+        /// Offer, with the dbus backend, to restart the slot which exited,
+        /// open its logs, or attach to its window. Default: true
+        pub actions: Option<bool>,
+
+        /// Opens a terminal, executing the command appended to it,
+        /// for the actions. Default: x-terminal-emulator -e
+        pub terminal: Option<String>,
+
+        /// Notifications kept in the history, the oldest dropped first.
+        /// Default: 1000. 0 keeps none.
+        pub history_limit: Option<usize>,
+
+        /// Alert rules for the stderr of all slots.
+        pub alerts: Option<Vec<super::Alert>>,
+
+        /// Per event. Missing fields are taken from the defaults.
+        pub events: Option<
+            std::collections::BTreeMap<crate::notify::Event, EventPolicy>,
+        >,
+    }
+
+    /// Of notifications of an event.
+    #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+    pub struct EventPolicy {
+        /// Default: true for exits, false for the rest.
+        /// Slots can override it.
+        pub enabled: Option<bool>,
+
+        /// Default: critical for exits, low or normal for the rest.
+        pub urgency: Option<crate::notify::Urgency>,
+
+        /// With {slot}, {code} and {session} placeholders.
+        pub summary: Option<String>,
+
+        /// Of the one let through after others were rate limited. With
+        /// {count} and {duration} placeholders too, e.g.
+        /// "{slot} exited {count} times in {duration}".
+        pub burst_summary: Option<String>,
     }
 }
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    iter::zip,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use expanduser::expanduser;
 
+use crate::notify;
+
+const VAR_HOSTNAME: &str = "hostname";
+
 #[derive(Debug)]
 pub struct Cfg {
     pub debug: bool,
@@ -23,28 +181,291 @@ pub struct Cfg {
     pub slots_fifos_dir: PathBuf,
     pub pista: Pista,
     pub notifications: Notifications,
+    pub logs: Logs,
+
+    /// User-defined values for {name} placeholders in slots.
+    pub vars: BTreeMap<String, String>,
+
+    /// Name of the profile applied to this config, if any.
+    pub profile: Option<String>,
+
+    /// Names of all profiles defined in the config.
+    pub profiles: Vec<String>,
+
+    /// Placeholders which expand_vars left as they were, since no variable
+    /// of their name is known, with the names of the slots they are in.
+    pub unknown_vars: Vec<(String, String)>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Notifications {
     pub log_lines_limit: usize,
     pub width_limit: usize,
     pub indent: String,
+    pub rate_limit_window: u64,
+    pub slot_rate_limit: Option<usize>,
+    pub global_rate_limit: Option<usize>,
+    pub coalesce_window: u64,
+    pub backend: Backend,
+    pub command: Option<String>,
+    pub file: Option<PathBuf>,
+    pub actions: bool,
+    pub terminal: String,
+    pub history_limit: usize,
+    pub alerts: Vec<Alert>,
+    pub events: BTreeMap<notify::Event, EventPolicy>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Logs {
+    pub max_size: Option<u64>,
+    pub max_age: Option<u64>,
+    pub keep: usize,
+    pub timestamps: bool,
+}
+
+impl Logs {
+    pub fn rotated(&self) -> bool {
+        self.max_size.is_some() || self.max_age.is_some()
+    }
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self {
+            max_size: None,
+            max_age: None,
+            keep: 5,
+            timestamps: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct EventPolicy {
+    pub enabled: bool,
+    pub urgency: notify::Urgency,
+    pub summary: String,
+    pub burst_summary: String,
+}
+
+impl Notifications {
+    /// Whether the event of the slot, if any, is notified of.
+    pub fn enabled(&self, event: notify::Event, slot: Option<&Slot>) -> bool {
+        slot.and_then(|s| s.notifications.get(&event))
+            .copied()
+            .unwrap_or_else(|| self.policy(event).enabled)
+    }
+
+    pub fn policy(&self, event: notify::Event) -> EventPolicy {
+        self.events
+            .get(&event)
+            .cloned()
+            .unwrap_or_else(|| EventPolicy::default(event))
+    }
+}
+
+impl EventPolicy {
+    /// Summary, with its placeholders expanded. Those which do not apply
+    /// to the event, like {code} of a session start, are left empty.
+    pub fn summary(
+        &self,
+        slot: Option<&str>,
+        code: Option<i32>,
+        session: &str,
+    ) -> Result<String> {
+        expand_summary(&self.summary, slot, code, session, None)
+    }
+
+    /// Burst summary, with its placeholders expanded, given the number of
+    /// the notifications summarized and the duration they span.
+    pub fn burst_summary(
+        &self,
+        slot: Option<&str>,
+        code: Option<i32>,
+        session: &str,
+        count: usize,
+        duration: &str,
+    ) -> Result<String> {
+        let burst = Some((count, duration));
+        expand_summary(&self.burst_summary, slot, code, session, burst)
+    }
+
+    fn default(event: notify::Event) -> Self {
+        use notify::{Event, Urgency};
+
+        let (enabled, urgency, summary, window, verb) = match event {
+            Event::PistaExited => {
+                (true, Urgency::Critical, "pista exited!", "pista", "exited")
+            }
+            Event::SlotExited => (
+                true,
+                Urgency::Critical,
+                "pista feed exited!",
+                "{slot}",
+                "exited",
+            ),
+            Event::SlotRecovered => (
+                false,
+                Urgency::Normal,
+                "pista feed recovered: {slot}",
+                "{slot}",
+                "recovered",
+            ),
+            Event::PistaRestarted => (
+                false,
+                Urgency::Normal,
+                "pista restarted",
+                "pista",
+                "restarted",
+            ),
+            Event::SessionStarted => (
+                false,
+                Urgency::Low,
+                "pista session started: {session}",
+                "{session}",
+                "started",
+            ),
+            Event::SessionStopped => (
+                false,
+                Urgency::Low,
+                "pista session stopped: {session}",
+                "{session}",
+                "stopped",
+            ),
+            Event::Alert => (
+                true,
+                Urgency::Normal,
+                "pista feed alert: {slot}",
+                "{slot}",
+                "alerted",
+            ),
+        };
+        Self {
+            enabled,
+            urgency,
+            summary: summary.to_string(),
+            burst_summary: format!(
+                "{} {} {{count}} times in {{duration}}",
+                window, verb
+            ),
+        }
+    }
+}
+
+/// Bursts, of a count and a duration, are only known to burst summaries.
+fn expand_summary(
+    template: &str,
+    slot: Option<&str>,
+    code: Option<i32>,
+    session: &str,
+    burst: Option<(usize, &str)>,
+) -> Result<String> {
+    vars::expand(template, |name| {
+        let val = match (name, burst) {
+            ("slot", _) => slot.unwrap_or_default().to_string(),
+            ("code", _) => code.map(|c| c.to_string()).unwrap_or_default(),
+            ("session", _) => session.to_string(),
+            ("count", Some((count, _))) => count.to_string(),
+            ("duration", Some((_, duration))) => duration.to_string(),
+            _ => bail!("Unknown placeholder: {{{}}}", name),
+        };
+        Ok(Some(val))
+    })
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            log_lines_limit: 10,
+            width_limit: 150,
+            indent: "    ".to_string(),
+            rate_limit_window: 300,
+            slot_rate_limit: None,
+            global_rate_limit: None,
+            coalesce_window: 0,
+            backend: Backend::default(),
+            command: None,
+            file: None,
+            actions: true,
+            terminal: "x-terminal-emulator -e".to_string(),
+            history_limit: 1000,
+            alerts: Vec::new(),
+            events: <notify::Event as clap::ValueEnum>::value_variants()
+                .iter()
+                .map(|e| (*e, EventPolicy::default(*e)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Spawn notify-send.
+    NotifySend,
+
+    /// Call org.freedesktop.Notifications on the session bus.
+    #[default]
+    Dbus,
+
+    /// Spawn the command, with the notification in JSON on stdin.
+    Command,
+
+    /// Append to the file.
+    File,
+
+    /// Do not notify.
+    None,
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// Options passed to pista, and the slots it displays.
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
 pub struct Pista {
     pub log_level: Option<PistaLogLevel>,
+
+    /// Set the X11 root window name, instead of printing to stdout.
     pub x11: Option<bool>,
+
+    /// Seconds between refreshes of the bar.
     pub interval: Option<f32>,
+
+    /// Displayed in place of the value of an expired slot.
     pub expiry_character: Option<char>,
+
+    /// Before each slot.
     pub pad_left: Option<String>,
+
+    /// After each slot.
     pub pad_right: Option<String>,
+
+    /// Between slots.
     pub separator: Option<String>,
+
+    /// In the order they appear in the bar.
+    #[serde(default)]
     pub slots: Vec<Slot>,
 }
 
-#[derive(Debug, Copy, Clone, serde::Deserialize)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
 pub enum PistaLogLevel {
     Nothing = 0,
     Error = 1,
@@ -53,22 +474,340 @@ pub enum PistaLogLevel {
     Debug = 4,
 }
 
-#[derive(Debug, serde::Deserialize)]
+/// A command, whose output lines are displayed in the bar.
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
 pub struct Slot {
+    /// Unique. Names the slot's directory and tmux window.
+    /// Defaults to the slot's position, so it cannot be a number.
     pub name: Option<String>,
+
+    /// Width in the bar. Defaults to the length of the first output line.
     pub len: Option<usize>,
+
+    /// Seconds after which the last output line expires.
     pub ttl: i32,
+
+    /// Script, executed by the interpreter.
     pub cmd: String,
 
+    /// Program executing cmd.
     #[serde(default = "default_interpreter")]
     pub interpreter: PathBuf,
+
+    /// Extra environment variables. Override those from env_file.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// File with KEY=VALUE lines, loaded before env.
+    pub env_file: Option<PathBuf>,
+
+    /// Start from an empty environment instead of inheriting tmux's.
+    #[serde(default)]
+    pub clear_env: bool,
+
+    /// Working directory of cmd. Defaults to the slot directory.
+    pub cwd: Option<PathBuf>,
+
+    /// Scheduling priority, as in renice -n.
+    #[schemars(range(min = -20, max = 19))]
+    pub nice: Option<i32>,
+
+    pub ionice: Option<IoNice>,
+
+    /// Written to /proc/<pid>/oom_score_adj. Range: -1000..=1000.
+    #[schemars(range(min = -1000, max = 1000))]
+    pub oom_score_adj: Option<i32>,
+
+    #[serde(default)]
+    pub limits: Limits,
+
+    /// Restrict filesystem access of cmd with Landlock.
+    pub sandbox: Option<Sandbox>,
+
+    /// Start the slot only if these conditions hold.
+    pub when: Option<When>,
+
+    /// Disabled slots are not started.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Name of the slot template to take the unset fields from.
+    pub extends: Option<String>,
+
+    /// Turns notifications of the slot's events on or off, regardless of
+    /// notifications.events, like {slot-recovered = true}.
+    #[serde(default)]
+    pub notifications: BTreeMap<notify::Event, bool>,
+
+    /// Alert rules for the slot's stderr, in addition to
+    /// notifications.alerts.
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+
+    /// Alert rules for the numbers in the slot's output.
+    #[serde(default)]
+    pub value_alerts: Vec<ValueAlert>,
+}
+
+/// Notifies of lines of a slot's stderr matching the pattern.
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+pub struct Alert {
+    /// Identifies the rule in notifications. Defaults to the pattern.
+    pub name: Option<String>,
+
+    /// Regular expression, searched for in each line,
+    /// without its terminal color codes.
+    pub pattern: String,
+
+    /// Seconds after an alert, during which further matches of the rule
+    /// are not notified. Counted from when the slot was last (re)started.
+    #[serde(default = "default_alert_cooldown")]
+    pub cooldown: u64,
+}
+
+impl Alert {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.pattern)
+    }
+}
+
+/// Notifies when a number in a slot's output lines crosses a threshold,
+/// like: {pattern = "(\\d+)%", below = 10, hysteresis = 5}
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+pub struct ValueAlert {
+    /// Identifies the rule in notifications. Defaults to the pattern.
+    pub name: Option<String>,
+
+    /// Regular expression, whose first capture group is the number.
+    pub pattern: String,
+
+    /// Alert when the number is above this. Exclusive with below.
+    pub above: Option<f64>,
+
+    /// Alert when the number is below this. Exclusive with above.
+    pub below: Option<f64>,
+
+    /// How far back past the threshold the number has to get, before the
+    /// rule alerts again, so that it does not on each update of a number
+    /// hovering around the threshold. Default: 0
+    #[serde(default)]
+    pub hysteresis: f64,
+}
+
+impl ValueAlert {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.pattern)
+    }
+
+    pub fn regex(&self) -> Result<regex::Regex> {
+        let regex = regex::Regex::new(&self.pattern)?;
+        if regex.captures_len() < 2 {
+            bail!("No capture group in pattern");
+        }
+        Ok(regex)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.regex()?;
+        match (self.above, self.below) {
+            (Some(_), None) | (None, Some(_)) => {}
+            _ => bail!("Exactly one of above or below is required"),
+        }
+        if self.hysteresis.is_nan() || self.hysteresis < 0.0 {
+            bail!("Invalid hysteresis: {}", self.hysteresis);
+        }
+        Ok(())
+    }
+}
+
+/// I/O scheduling class and priority, as in ionice.
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub struct IoNice {
+    /// Default: BestEffort
+    #[serde(default)]
+    pub class: IoNiceClass,
+
+    /// 0 (highest) to 7 (lowest). Ignored by the Idle class.
+    #[schemars(range(max = 7))]
+    pub level: Option<u8>,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub enum IoNiceClass {
+    Realtime = 1,
+    #[default]
+    BestEffort = 2,
+    Idle = 3,
+}
+
+#[derive(
+    Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+pub struct Sandbox {
+    /// Paths allowed to be read and executed.
+    #[serde(default)]
+    pub read: Vec<PathBuf>,
+
+    /// Paths allowed to be read, written and executed.
+    /// The slot directory is always included.
+    #[serde(default)]
+    pub write: Vec<PathBuf>,
+
+    /// Also allow reading system paths, like /usr and /etc,
+    /// without which hardly any program can start.
+    #[serde(default = "default_true")]
+    pub system: bool,
+}
+
+/// Resource limits (setrlimit) of the slot's cmd.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub struct Limits {
+    /// Bytes of virtual memory. RLIMIT_AS
+    pub address_space: Option<u64>,
+
+    /// RLIMIT_NOFILE
+    pub open_files: Option<u64>,
+
+    /// Seconds of CPU time, after which SIGXCPU is sent. RLIMIT_CPU
+    pub cpu_seconds: Option<u64>,
+}
+
+/// Files merged with their includes, as written, before anything
+/// is resolved.
+pub fn merged(paths: &[PathBuf]) -> Result<toml::Table> {
+    load::load(paths)
+}
+
+/// JSON Schema of the config file format, for editors.
+pub fn schema() -> schemars::schema::RootSchema {
+    let mut schema = schemars::schema_for!(file::Cfg);
+    schema.schema.metadata().title = Some(crate::NAME!().to_string());
+    schema
+}
+
+/// Whether the path leads to a key of the config file format. Slots,
+/// and other arrays, are indexed by name, like pista.slots.<name>.ttl.
+pub fn is_key(path: &[&str]) -> bool {
+    let root = schema();
+    is_key_in(
+        &schemars::schema::Schema::Object(root.schema),
+        &root.definitions,
+        path,
+    )
+}
+
+fn is_key_in(
+    schema: &schemars::schema::Schema,
+    definitions: &schemars::Map<String, schemars::schema::Schema>,
+    path: &[&str],
+) -> bool {
+    use schemars::schema::{Schema, SingleOrVec};
+
+    let schema = match schema {
+        Schema::Bool(any) => return *any,
+        Schema::Object(schema) => schema,
+    };
+    if let Some(name) = schema
+        .reference
+        .as_deref()
+        .and_then(|r| r.strip_prefix("#/definitions/"))
+    {
+        return definitions
+            .get(name)
+            .is_some_and(|s| is_key_in(s, definitions, path));
+    }
+    let [key, rest @ ..] = path else {
+        return true;
+    };
+    let alternatives = schema.subschemas.iter().flat_map(|s| {
+        [&s.all_of, &s.any_of, &s.one_of]
+            .into_iter()
+            .flatten()
+            .flatten()
+    });
+    if alternatives
+        .into_iter()
+        .any(|s| is_key_in(s, definitions, path))
+    {
+        return true;
+    }
+    if let Some(object) = &schema.object {
+        if let Some(property) = object.properties.get(*key) {
+            return is_key_in(property, definitions, rest);
+        }
+        if let Some(additional) = &object.additional_properties {
+            return is_key_in(additional, definitions, rest);
+        }
+    }
+    match schema.array.as_ref().and_then(|a| a.items.as_ref()) {
+        Some(SingleOrVec::Single(items)) => {
+            is_key_in(items, definitions, rest)
+        }
+        _ => false,
+    }
 }
 
 impl Cfg {
-    pub fn from_file(path: &Path) -> Result<Self> {
-        let data: String = crate::fs::read_to_string(path)?;
-        let file: file::Cfg = toml::from_str(&data).with_context(|| {
-            format!("Failed to parse TOML from: {:?}", path)
+    /// Files are merged in the given order. Profile, if not given,
+    /// is looked-up in the overrides, then in the merged result.
+    /// Overrides are applied last, so they can change the profile's slots.
+    pub fn from_files(
+        paths: &[PathBuf],
+        profile: Option<&str>,
+        overrides: &[overrides::Override],
+    ) -> Result<Self> {
+        let mut table = load::load(paths)?;
+        let profile = profile
+            .map(String::from)
+            .or_else(|| {
+                overrides
+                    .iter()
+                    .rev()
+                    .find(|(k, _)| k == "profile")
+                    .map(|(_, v)| v.clone())
+            })
+            .or(table
+                .get("profile")
+                .and_then(toml::Value::as_str)
+                .map(String::from));
+        if let Some(ref name) = profile {
+            profile::apply(&mut table, name)?;
+        }
+        overrides::apply(&mut table, overrides)?;
+        templates::resolve(&mut table)?;
+        let file: file::Cfg = table.try_into().with_context(|| {
+            format!("Invalid config in: {:?}, merged with includes", paths)
+        })?;
+        templates::validate(&file).with_context(|| {
+            format!("Invalid config in: {:?}, merged with includes", paths)
         })?;
         let default = Self::default()?;
         let cfg = Self {
@@ -81,9 +820,85 @@ impl Cfg {
                     Some(d) => expanduser(d)?,
                 }
             },
-            notifications: file.notifications.unwrap_or(default.notifications),
+            notifications: match file.notifications {
+                None => default.notifications,
+                Some(n) => Notifications {
+                    log_lines_limit: n
+                        .log_lines_limit
+                        .unwrap_or(default.notifications.log_lines_limit),
+                    width_limit: n
+                        .width_limit
+                        .unwrap_or(default.notifications.width_limit),
+                    indent: n.indent.unwrap_or(default.notifications.indent),
+                    rate_limit_window: n
+                        .rate_limit_window
+                        .unwrap_or(default.notifications.rate_limit_window),
+                    slot_rate_limit: n.slot_rate_limit,
+                    global_rate_limit: n.global_rate_limit,
+                    coalesce_window: n
+                        .coalesce_window
+                        .unwrap_or(default.notifications.coalesce_window),
+                    backend: n
+                        .backend
+                        .unwrap_or(default.notifications.backend),
+                    command: n.command,
+                    file: n.file.map(expanduser).transpose()?,
+                    actions: n
+                        .actions
+                        .unwrap_or(default.notifications.actions),
+                    terminal: n
+                        .terminal
+                        .unwrap_or(default.notifications.terminal),
+                    history_limit: n
+                        .history_limit
+                        .unwrap_or(default.notifications.history_limit),
+                    alerts: n.alerts.unwrap_or_default(),
+                    events: {
+                        let mut events = default.notifications.events;
+                        for (event, p) in n.events.unwrap_or_default() {
+                            let default = EventPolicy::default(event);
+                            events.insert(
+                                event,
+                                EventPolicy {
+                                    enabled: p
+                                        .enabled
+                                        .unwrap_or(default.enabled),
+                                    urgency: p
+                                        .urgency
+                                        .unwrap_or(default.urgency),
+                                    summary: p
+                                        .summary
+                                        .unwrap_or(default.summary),
+                                    burst_summary: p
+                                        .burst_summary
+                                        .unwrap_or(default.burst_summary),
+                                },
+                            );
+                        }
+                        events
+                    },
+                },
+            },
+            logs: match file.logs {
+                None => default.logs,
+                Some(l) => Logs {
+                    max_size: l.max_size,
+                    max_age: l.max_age,
+                    keep: l.keep.unwrap_or(default.logs.keep),
+                    timestamps: l
+                        .timestamps
+                        .unwrap_or(default.logs.timestamps),
+                },
+            },
             pista: file.pista.unwrap_or(default.pista),
+            vars: file.vars.unwrap_or(default.vars),
+            profile: profile.or(file.profile),
+            profiles: file
+                .profiles
+                .map_or(default.profiles, |p| p.into_keys().collect()),
+            unknown_vars: default.unknown_vars,
         };
+        cfg.validate()?;
         Ok(cfg)
     }
 
@@ -94,11 +909,8 @@ impl Cfg {
             sock: name.to_string(),
             session: name.to_string(),
             slots_fifos_dir: expanduser(format!("~/.{}/slots", name))?,
-            notifications: Notifications {
-                log_lines_limit: 10,
-                width_limit: 150,
-                indent: "    ".to_string(),
-            },
+            notifications: Notifications::default(),
+            logs: Logs::default(),
             pista: Pista {
                 interval: None,
                 pad_left: None,
@@ -109,8 +921,269 @@ impl Cfg {
                 expiry_character: None,
                 slots: vec![],
             },
+            vars: BTreeMap::new(),
+            profile: None,
+            profiles: vec![],
+            unknown_vars: vec![],
         })
     }
+
+    fn validate(&self) -> Result<()> {
+        if self.logs.max_size == Some(0) || self.logs.max_age == Some(0) {
+            bail!("Log rotation limits must be positive");
+        }
+        let notif = &self.notifications;
+        match notif.backend {
+            Backend::Command if notif.command.is_none() => {
+                bail!("Notifications backend command requires: command")
+            }
+            Backend::File if notif.file.is_none() => {
+                bail!("Notifications backend file requires: file")
+            }
+            _ => {}
+        }
+        for (event, policy) in &notif.events {
+            policy.summary(None, None, "").with_context(|| {
+                format!("Invalid summary of notifications of: {:?}", event)
+            })?;
+            policy.burst_summary(None, None, "", 0, "").with_context(
+                || {
+                    format!(
+                        "Invalid burst summary of notifications of: {:?}",
+                        event
+                    )
+                },
+            )?;
+        }
+        for alert in &notif.alerts {
+            regex::Regex::new(&alert.pattern).with_context(|| {
+                format!("Invalid alert pattern: {:?}", &alert.pattern)
+            })?;
+        }
+        let mut names = HashSet::new();
+        for slot in &self.pista.slots {
+            for alert in &slot.alerts {
+                regex::Regex::new(&alert.pattern).with_context(|| {
+                    format!(
+                        "Invalid alert pattern: {:?}, of slot: {:?}",
+                        &alert.pattern, &slot.name
+                    )
+                })?;
+            }
+            for alert in &slot.value_alerts {
+                alert.validate().with_context(|| {
+                    format!(
+                        "Invalid value alert: {:?}, of slot: {:?}",
+                        alert.name(),
+                        &slot.name
+                    )
+                })?;
+            }
+            if let Some(ref name) = slot.name {
+                // Numbers name the unnamed slots, by their position.
+                if name == crate::cmd::NAME_PISTA
+                    || name.bytes().all(|b| b.is_ascii_digit())
+                {
+                    bail!("Reserved slot name: {:?}", name);
+                }
+                if name.is_empty() || name.contains(['/', ' ']) {
+                    bail!("Invalid slot name: {:?}", name);
+                }
+                if !names.insert(name) {
+                    bail!("Duplicate slot name: {:?}", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// In the format of the config file, with all values resolved.
+    pub fn to_toml(&self) -> Result<String> {
+        #[derive(serde::Serialize)]
+        struct Effective<'a> {
+            version: i64,
+            debug: bool,
+            sock_name: &'a str,
+            session: &'a str,
+            slots_fifos_dir: &'a Path,
+            profile: Option<&'a str>,
+            vars: &'a BTreeMap<String, String>,
+            notifications: &'a Notifications,
+            logs: &'a Logs,
+            pista: &'a Pista,
+        }
+        let effective = Effective {
+            version: migrate::VERSION,
+            debug: self.debug,
+            sock_name: &self.sock,
+            session: &self.session,
+            slots_fifos_dir: &self.slots_fifos_dir,
+            profile: self.profile.as_deref(),
+            vars: &self.vars,
+            notifications: &self.notifications,
+            logs: &self.logs,
+            pista: &self.pista,
+        };
+        Ok(toml::to_string_pretty(&effective)?)
+    }
+
+    /// Name and directory of the slot at the given position, counting from 1.
+    /// Unnamed slots are named after their position.
+    pub fn slot_name_and_dir(
+        &self,
+        position: usize,
+        slot: &Slot,
+    ) -> (String, PathBuf) {
+        let name = match slot.name {
+            None => position.to_string(),
+            Some(ref name) => name.to_string(),
+        };
+        let dir = self.slots_fifos_dir.join(&name);
+        (name, dir)
+    }
+
+    /// Expands placeholders in slot cmd and env values. Must be called after
+    /// all other changes to the config, since slot directories depend on it.
+    ///
+    /// Available are user-defined vars and built-ins:
+    /// {slot.name}, {slot.dir}, {slot.fifo}, {hostname} and {env.X}.
+    pub fn expand_vars(&mut self) -> Result<()> {
+        for name in self.vars.keys() {
+            if name.contains('.') || name == VAR_HOSTNAME {
+                bail!("Reserved variable name: {:?}", name);
+            }
+        }
+        let mut hostname = None;
+        let mut unknown_vars = Vec::new();
+        let mut slots = std::mem::take(&mut self.pista.slots);
+        for (position, slot) in zip(1.., slots.iter_mut()) {
+            let (slot_name, slot_dir) = self.slot_name_and_dir(position, slot);
+            let mut unknown = BTreeSet::new();
+            let mut lookup = |name: &str| -> Result<Option<String>> {
+                let val = match name {
+                    "slot.name" => Some(slot_name.clone()),
+                    "slot.dir" => Some(slot_dir.to_string_lossy().to_string()),
+                    "slot.fifo" => Some(
+                        slot_dir
+                            .join(crate::cmd::NAME_OUT)
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    VAR_HOSTNAME => match hostname {
+                        Some(ref h) => Some(String::clone(h)),
+                        None => {
+                            let h = crate::process::hostname()?;
+                            hostname = Some(h.clone());
+                            Some(h)
+                        }
+                    },
+                    _ if name.starts_with("slot.") => {
+                        bail!("Unknown variable: {{{}}}", name)
+                    }
+                    _ => match name.strip_prefix("env.") {
+                        Some(var) => {
+                            Some(std::env::var(var).with_context(|| {
+                                format!("Environment variable: {:?}", var)
+                            })?)
+                        }
+                        // Others, like awk's {print}, are not ours.
+                        None => {
+                            let val = self.vars.get(name).cloned();
+                            if val.is_none() {
+                                unknown.insert(name.to_string());
+                            }
+                            val
+                        }
+                    },
+                };
+                Ok(val)
+            };
+            let context = || {
+                format!("Failed to expand variables in slot {:?}", &slot_name)
+            };
+            slot.cmd =
+                vars::expand(&slot.cmd, &mut lookup).with_context(context)?;
+            for val in slot.env.values_mut() {
+                *val = vars::expand(val, &mut lookup).with_context(context)?;
+            }
+            unknown_vars
+                .extend(unknown.into_iter().map(|v| (slot_name.clone(), v)));
+        }
+        self.pista.slots = slots;
+        self.unknown_vars = unknown_vars;
+        Ok(())
+    }
+}
+
+impl Slot {
+    /// Variables from env_file, followed by those from env.
+    pub fn env_vars(&self) -> Result<Vec<(String, String)>> {
+        let mut vars = BTreeMap::new();
+        if let Some((env_file, data)) = self.env_file_data()? {
+            vars.extend(parse_env_file(&data).with_context(|| {
+                format!("Invalid env file: {:?}", &env_file)
+            })?);
+        }
+        vars.extend(self.env.clone());
+        Ok(vars.into_iter().collect())
+    }
+
+    /// Path, with ~ expanded, and contents of env_file, if any.
+    pub fn env_file_data(&self) -> Result<Option<(PathBuf, String)>> {
+        let env_file = match self.env_file {
+            None => return Ok(None),
+            Some(ref env_file) => expanduser(env_file.to_string_lossy())?,
+        };
+        let data = crate::fs::read_to_string(&env_file)?;
+        Ok(Some((env_file, data)))
+    }
+
+    /// Read and write paths of the sandbox, if any.
+    #[allow(clippy::type_complexity)]
+    pub fn sandbox_paths(
+        &self,
+        slot_dir: &Path,
+    ) -> Result<Option<(Vec<PathBuf>, Vec<PathBuf>)>> {
+        let sandbox = match self.sandbox {
+            None => return Ok(None),
+            Some(ref sandbox) => sandbox,
+        };
+        let expand = |paths: &[PathBuf]| -> Result<Vec<PathBuf>> {
+            paths
+                .iter()
+                .map(|p| Ok(expanduser(p.to_string_lossy())?))
+                .collect()
+        };
+        let mut read = expand(&sandbox.read)?;
+        let mut write = expand(&sandbox.write)?;
+        write.push(slot_dir.to_path_buf());
+        if sandbox.system {
+            read.extend(crate::sandbox::SYSTEM_READ.map(PathBuf::from));
+            write.extend(crate::sandbox::SYSTEM_WRITE.map(PathBuf::from));
+        }
+        if let Some(cwd) = self.cwd()? {
+            read.push(cwd);
+        }
+        Ok(Some((read, write)))
+    }
+
+    /// Reason to not start the slot, if any.
+    pub fn skip_reason(&self) -> Result<Option<String>> {
+        if !self.enabled {
+            return Ok(Some("disabled".to_string()));
+        }
+        match self.when {
+            None => Ok(None),
+            Some(ref when) => when.unmet(),
+        }
+    }
+
+    pub fn cwd(&self) -> Result<Option<PathBuf>> {
+        match self.cwd {
+            None => Ok(None),
+            Some(ref cwd) => Ok(Some(expanduser(cwd.to_string_lossy())?)),
+        }
+    }
 }
 
 impl Pista {
@@ -158,3 +1231,162 @@ impl Pista {
 fn default_interpreter() -> PathBuf {
     PathBuf::from("/bin/bash")
 }
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_alert_cooldown() -> u64 {
+    300
+}
+
+/// Parses lines of KEY=VALUE, skipping blanks and # comments.
+/// Accepts an optional "export " prefix and quotes around the value.
+fn parse_env_file(data: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (i, line) in zip(1.., data.lines()) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, val) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Line {}: missing '=': {:?}", i, line))?;
+        let key = key.trim();
+        if key.is_empty() {
+            bail!("Line {}: empty variable name: {:?}", i, line);
+        }
+        let val = val.trim();
+        let val = ['"', '\'']
+            .iter()
+            .find_map(|q| val.strip_prefix(*q)?.strip_suffix(*q))
+            .unwrap_or(val);
+        vars.push((key.to_string(), val.to_string()));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_env_file() {
+        let data = "
+            # comment
+            A=1
+            export B = two
+            C=\"three 3\"
+            D='$four'
+            E=
+        ";
+        assert_eq!(
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "two".to_string()),
+                ("C".to_string(), "three 3".to_string()),
+                ("D".to_string(), "$four".to_string()),
+                ("E".to_string(), "".to_string()),
+            ],
+            super::parse_env_file(data).unwrap()
+        );
+        assert!(super::parse_env_file("A").is_err());
+        assert!(super::parse_env_file("=1").is_err());
+    }
+
+    #[test]
+    fn expand_vars() {
+        let path = std::env::temp_dir()
+            .join(format!("pistactl-test-vars-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            slots_fifos_dir = "/tmp/pistactl-test"
+            vars = {n = "3"}
+            [pista]
+            slots = [
+                {name = "a", ttl = 1, cmd = "echo x | awk '{print}' | head -{n}"},
+                {name = "b", ttl = 1, cmd = "echo {slot.nmae}"},
+            ]
+            "#,
+        )
+        .unwrap();
+        let cfg =
+            || super::Cfg::from_files(std::slice::from_ref(&path), None, &[]);
+        let mut a = cfg().unwrap();
+        a.pista.slots.truncate(1);
+        a.expand_vars().unwrap();
+        assert_eq!("echo x | awk '{print}' | head -3", a.pista.slots[0].cmd);
+        assert_eq!(
+            vec![("a".to_string(), "print".to_string())],
+            a.unknown_vars
+        );
+        assert!(cfg().unwrap().expand_vars().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn slot_names() {
+        let path = std::env::temp_dir()
+            .join(format!("pistactl-test-names-{}.toml", std::process::id()));
+        let cfg = |name: &str| {
+            let slots = format!(
+                "[pista]\nslots = [{{ttl = 1, cmd = \"a\"}}, \
+                {{name = {:?}, ttl = 1, cmd = \"b\"}}]",
+                name
+            );
+            std::fs::write(&path, slots).unwrap();
+            super::Cfg::from_files(std::slice::from_ref(&path), None, &[])
+        };
+        assert!(cfg("a1").is_ok());
+        assert!(cfg("1").is_err());
+        assert!(cfg("01").is_err());
+        assert!(cfg("pista").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn is_key() {
+        for key in [
+            "debug",
+            "pista.separator",
+            "pista.slots.time.ttl",
+            "pista.slots.time.limits.open_files",
+            "notifications.events.slot-exited.urgency",
+            "slot_templates.net.ttl",
+            "vars.host",
+        ] {
+            let path: Vec<&str> = key.split('.').collect();
+            assert!(super::is_key(&path), "{}", key);
+        }
+        for key in ["nope", "pista.nope", "pista.slots.time.nope", "debug.x"] {
+            let path: Vec<&str> = key.split('.').collect();
+            assert!(!super::is_key(&path), "{}", key);
+        }
+    }
+
+    #[test]
+    fn burst_summary() {
+        use crate::notify::Event;
+
+        let policy = super::EventPolicy::default(Event::SlotExited);
+        assert_eq!(
+            "weather exited 12 times in 5 min",
+            policy
+                .burst_summary(Some("weather"), Some(1), "s", 12, "5 min")
+                .unwrap()
+        );
+        let policy = super::EventPolicy {
+            burst_summary: "{session}: {count}x {code}".to_string(),
+            ..super::EventPolicy::default(Event::PistaExited)
+        };
+        assert_eq!(
+            "s: 3x 1",
+            policy.burst_summary(None, Some(1), "s", 3, "1 s").unwrap()
+        );
+        let policy = super::EventPolicy {
+            summary: "{count}".to_string(),
+            ..policy
+        };
+        assert!(policy.summary(None, None, "s").is_err());
+    }
+}
@@ -0,0 +1,1179 @@
+//! Changes to config files, which preserve comments and formatting.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use toml_edit::{
+    Array, ArrayOfTables, Document, InlineTable, Item, Table, TableLike, Value,
+};
+
+const BACKUP_TIME_FORMAT: &[time::format_description::FormatItem] = time::macros::format_description!(
+    "[year][month][day]T[hour][minute][second]"
+);
+
+pub struct Doc {
+    pub path: PathBuf,
+    pub doc: Document,
+}
+
+impl Doc {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = crate::fs::read_to_string(path)?;
+        let doc = data.parse::<Document>().with_context(|| {
+            format!("Failed to parse TOML from: {:?}", path)
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            doc,
+        })
+    }
+
+    /// Replaces the file atomically, so that a failure midway does not
+    /// leave behind a truncated config.
+    pub fn save(&self) -> Result<()> {
+        let tmp = self.path.with_extension("toml.tmp");
+        std::fs::write(&tmp, self.doc.to_string())
+            .with_context(|| format!("Failed to write to: {:?}", &tmp))?;
+        std::fs::rename(&tmp, &self.path).with_context(|| {
+            format!("Failed to rename {:?} to {:?}", &tmp, &self.path)
+        })
+    }
+
+    /// Saves the change after backing-up the previous version, then
+    /// validates the whole config, made of cfg_files, since the changed
+    /// file may be any one of them, or of their includes. An invalid
+    /// change is rolled back.
+    pub fn commit(&self, cfg_files: &[PathBuf]) -> Result<()> {
+        let backup = backup(&self.path)?;
+        self.save()?;
+        if let Err(e) = super::Cfg::from_files(cfg_files, None, &[]) {
+            std::fs::rename(&backup, &self.path).with_context(|| {
+                format!(
+                    "Failed to restore {:?} from {:?}",
+                    &self.path, &backup
+                )
+            })?;
+            return Err(e.context(format!(
+                "Change rejected and not saved to: {:?}",
+                &self.path
+            )));
+        }
+        tracing::info!("Updated config file: {:?}", &self.path);
+        tracing::info!("Previous version backed-up to: {:?}", &backup);
+        Ok(())
+    }
+
+    /// Calls f with the table of the named slot, if it is found.
+    pub fn with_slot<T>(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut dyn TableLike) -> T,
+    ) -> Option<T> {
+        let is_named = |t: &dyn TableLike| {
+            t.get("name").and_then(Item::as_str) == Some(name)
+        };
+        let slots = self.doc.get_mut("pista")?.get_mut("slots")?;
+        if let Some(slots) = slots.as_array_mut() {
+            let slot = slots
+                .iter_mut()
+                .filter_map(|v| v.as_inline_table_mut())
+                .find(|t| is_named(*t))?;
+            return Some(f(slot));
+        }
+        if let Some(slots) = slots.as_array_of_tables_mut() {
+            let slot = slots.iter_mut().find(|t| is_named(*t))?;
+            return Some(f(slot));
+        }
+        None
+    }
+
+    pub fn has_slot(&mut self, name: &str) -> bool {
+        self.with_slot(name, |_| ()).is_some()
+    }
+
+    fn slots(&mut self) -> Option<Slots<'_>> {
+        let slots = self.doc.get_mut("pista")?.get_mut("slots")?;
+        match slots {
+            Item::Value(Value::Array(a)) => Some(Slots::Inline(a)),
+            Item::ArrayOfTables(a) => Some(Slots::Tables(a)),
+            _ => None,
+        }
+    }
+
+    fn slots_or_err(&mut self) -> Result<Slots<'_>> {
+        let path = self.path.clone();
+        self.slots().ok_or_else(|| {
+            anyhow!("No array of slots in [pista] section of: {:?}", path)
+        })
+    }
+}
+
+/// Either of the two ways to write the slots array:
+///
+/// ```toml
+/// slots = [{name = "a", ...}, ...]
+/// ```
+///
+/// or
+///
+/// ```toml
+/// [[pista.slots]]
+/// name = "a"
+/// ```
+enum Slots<'a> {
+    Inline(&'a mut Array),
+    Tables(&'a mut ArrayOfTables),
+}
+
+impl<'a> Slots<'a> {
+    fn position(&self, name: &str) -> Option<usize> {
+        let is_named = |t: &dyn TableLike| {
+            t.get("name").and_then(Item::as_str) == Some(name)
+        };
+        match self {
+            Self::Inline(a) => a.iter().position(|v| {
+                v.as_inline_table().is_some_and(|t| is_named(t))
+            }),
+            Self::Tables(a) => a.iter().position(|t| is_named(t)),
+        }
+    }
+
+    fn position_or_err(&self, name: &str) -> Result<usize> {
+        self.position(name)
+            .ok_or_else(|| anyhow!("Slot not found: {:?}", name))
+    }
+
+    fn remove(&mut self, i: usize) {
+        match self {
+            Self::Inline(a) => {
+                let mut rows = Rows::take(a);
+                rows.rows.remove(i);
+                rows.put(a);
+            }
+            Self::Tables(a) => {
+                let mut tables = take_tables(a);
+                tables.remove(i);
+                put_tables(a, tables);
+            }
+        }
+    }
+
+    fn move_to(&mut self, from: usize, to: usize) {
+        match self {
+            Self::Inline(a) => {
+                let mut rows = Rows::take(a);
+                let row = rows.rows.remove(from);
+                rows.rows.insert(to, row);
+                rows.put(a);
+            }
+            Self::Tables(a) => {
+                let mut tables = take_tables(a);
+                let table = tables.remove(from);
+                tables.insert(to, table);
+                put_tables(a, tables);
+            }
+        }
+    }
+}
+
+/// Slots of an inline array, each with its comments: the lines before it,
+/// and the rest of the line after its comma, which toml_edit keeps in the
+/// prefix of the next one, or, for the last one, in the layout before the
+/// closing bracket. So that, removed or moved, a slot takes its comments
+/// along and leaves the others in place.
+struct Rows {
+    /// Rest of the line of the opening bracket.
+    opener: String,
+
+    /// Lines before, the slot, and the rest of its line.
+    rows: Vec<(String, Value, String)>,
+
+    /// Lines before the closing bracket.
+    closer: String,
+
+    /// Of an array on one line, the spacing before each slot, which,
+    /// unlike comments, stays in place.
+    spacing: Option<Vec<String>>,
+}
+
+impl Rows {
+    fn take(a: &Array) -> Self {
+        let values: Vec<Value> = a.iter().cloned().collect();
+        let end = match values.last() {
+            Some(last) if !a.trailing_comma() => raw(last.decor().suffix()),
+            _ => raw(Some(a.trailing())),
+        };
+        let prefixes: Vec<String> =
+            values.iter().map(|v| raw(v.decor().prefix())).collect();
+        let (opener, _) = split_line(prefixes.first().unwrap_or(&end));
+        let rows = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let (_, lines) = split_line(&prefixes[i]);
+                let next = prefixes.get(i + 1).unwrap_or(&end);
+                let (line, _) = split_line(next);
+                (lines.to_string(), value, line.to_string())
+            })
+            .collect();
+        let (_, closer) = split_line(&end);
+        let one_line =
+            !end.contains('\n') && prefixes.iter().all(|p| !p.contains('\n'));
+        Self {
+            opener: opener.to_string(),
+            rows,
+            closer: closer.to_string(),
+            spacing: one_line.then_some(prefixes),
+        }
+    }
+
+    fn put(self, a: &mut Array) {
+        let trailing_comma = a.trailing_comma() && !self.rows.is_empty();
+        a.clear();
+        let mut line = self.opener;
+        for (i, (mut lines, mut value, next_line)) in
+            self.rows.into_iter().enumerate()
+        {
+            if let Some(ref spacing) = self.spacing {
+                lines = spacing.get(i).cloned().unwrap_or_else(|| " ".into());
+            }
+            value.decor_mut().set_prefix(line + &lines);
+            value.decor_mut().set_suffix("");
+            a.push_formatted(value);
+            line = next_line;
+        }
+        let end = line + &self.closer;
+        match a.iter_mut().last() {
+            Some(last) if !trailing_comma => {
+                last.decor_mut().set_suffix(end);
+                a.set_trailing("");
+            }
+            _ => a.set_trailing(end),
+        }
+        a.set_trailing_comma(trailing_comma);
+    }
+}
+
+/// The first line, with its newline, and the rest. All is the rest,
+/// if there is no newline, since then it is not on a line of its own.
+fn split_line(s: &str) -> (&str, &str) {
+    match s.find('\n') {
+        Some(i) => s.split_at(i + 1),
+        None => ("", s),
+    }
+}
+
+pub(super) fn raw(s: Option<&toml_edit::RawString>) -> String {
+    s.and_then(toml_edit::RawString::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn take_tables(a: &mut ArrayOfTables) -> Vec<Table> {
+    let tables = a.iter().cloned().collect();
+    a.clear();
+    tables
+}
+
+/// Document order of tables is determined by their positions, not by
+/// their order in the array, so the positions of all the tables of the
+/// slots, including their sub-tables, like [pista.slots.when], are
+/// reassigned in the new order. New tables are positioned after all others.
+fn put_tables(a: &mut ArrayOfTables, mut tables: Vec<Table>) {
+    let mut positions = Vec::new();
+    let mut unpositioned = 0;
+    for table in tables.iter_mut() {
+        visit_tables(table, &mut |t| match t.position() {
+            Some(p) => positions.push(p),
+            None => unpositioned += 1,
+        });
+    }
+    let next = positions.iter().max().map_or(0, |p| p + 1);
+    positions.extend(next..next + unpositioned);
+    positions.sort();
+    let mut positions = positions.into_iter();
+    for mut table in tables {
+        visit_tables(&mut table, &mut |t| {
+            if let Some(p) = positions.next() {
+                t.set_position(p);
+            }
+        });
+        a.push(table);
+    }
+}
+
+fn visit_tables(table: &mut Table, f: &mut impl FnMut(&mut Table)) {
+    f(table);
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(t) => visit_tables(t, f),
+            Item::ArrayOfTables(a) => {
+                for t in a.iter_mut() {
+                    visit_tables(t, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The user's file, which overrides the system-wide ones, and the files
+/// to validate a change to it with. If only system-wide files were found,
+/// the user's file is created, to be layered on top of them.
+fn user_file(cfg_files: &[PathBuf]) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let last = cfg_files
+        .last()
+        .ok_or_else(|| anyhow!("No config file to edit"))?;
+    // The user's, the legacy one, or the one given on command line.
+    if !super::paths::system().contains(last) {
+        return Ok((last.clone(), cfg_files.to_vec()));
+    }
+    let user = super::paths::user()?;
+    if let Some(dir) = user.parent() {
+        crate::fs::create_dir_all(dir)?;
+    }
+    // Not included, since all system-wide files are layered beneath it
+    // anyway, while an included file would override it.
+    let mut data = String::from("# Layered on top of:\n");
+    for path in cfg_files {
+        data.push_str(&format!("# - {}\n", path.display()));
+    }
+    // For the comment to stay on top, rather than go after new tables.
+    data.push_str(&format!("version = {}\n", super::migrate::VERSION));
+    std::fs::write(&user, data)
+        .with_context(|| format!("Failed to write to: {:?}", &user))?;
+    tracing::info!("Created user's config file: {:?}", &user);
+    let mut files = cfg_files.to_vec();
+    files.push(user.clone());
+    Ok((user, files))
+}
+
+/// Copies the file to one with a timestamp in its name.
+fn backup(path: &Path) -> Result<PathBuf> {
+    let now = time::OffsetDateTime::now_local()
+        .unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+    let stamp = now.format(BACKUP_TIME_FORMAT)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut backup = path.with_file_name(format!("{}.{}.bak", name, stamp));
+    // More than one change within a second must not overwrite the backup
+    // of the version before the first one.
+    for i in 1.. {
+        if !backup.exists() {
+            break;
+        }
+        backup = path.with_file_name(format!("{}.{}.{}.bak", name, stamp, i));
+    }
+    std::fs::copy(path, &backup).with_context(|| {
+        format!("Failed to backup {:?} to {:?}", path, &backup)
+    })?;
+    Ok(backup)
+}
+
+/// Finds the file defining the named slot. When more than one does,
+/// it is the last one in merge order, since its definition is in effect.
+pub fn find_slot(cfg_files: &[PathBuf], name: &str) -> Result<Doc> {
+    let mut found = None;
+    for (path, _) in super::load::layers(cfg_files)? {
+        let mut doc = Doc::open(&path)?;
+        if doc.has_slot(name) {
+            found = Some(doc);
+        }
+    }
+    found.ok_or_else(|| {
+        anyhow!(
+            "Slot not found: {:?}, in: {:?}, or their includes",
+            name,
+            cfg_files
+        )
+    })
+}
+
+/// Sets the enabled flag of the named slot, in the file which defines it.
+pub fn set_slot_enabled(
+    cfg_files: &[PathBuf],
+    name: &str,
+    enabled: bool,
+) -> Result<()> {
+    let mut doc = find_slot(cfg_files, name)?;
+    doc.with_slot(name, |slot| {
+        if enabled {
+            // Enabled is the default, so no need to clutter the config.
+            slot.remove("enabled");
+        } else {
+            insert_value(slot, "enabled", Value::from(false));
+        }
+    });
+    doc.commit(cfg_files)
+}
+
+/// Adds the slot to the user's file, rather than to any of its includes,
+/// at the end, or before the named one.
+pub fn add_slot(
+    cfg_files: &[PathBuf],
+    slot: &[(&str, Value)],
+    before: Option<&str>,
+) -> Result<()> {
+    let (path, cfg_files) = user_file(cfg_files)?;
+    let mut doc = Doc::open(&path)?;
+    if let Some((_, name)) = slot.iter().find(|(k, _)| *k == "name") {
+        if let Some(name) = name.as_str() {
+            if doc.has_slot(name) {
+                bail!("Slot already exists: {:?}", name);
+            }
+        }
+    }
+    if doc.slots().is_none() {
+        let pista = doc
+            .doc
+            .entry("pista")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("Not a table: pista"))?;
+        let mut slots = Array::new();
+        slots.set_trailing("\n");
+        slots.set_trailing_comma(true);
+        pista.insert("slots", Item::Value(Value::Array(slots)));
+    }
+    let mut slots = doc.slots_or_err()?;
+    let i = match before {
+        None => None,
+        Some(before) => Some(slots.position_or_err(before)?),
+    };
+    match slots {
+        Slots::Inline(a) => {
+            let i = i.unwrap_or(a.len());
+            let rows: Vec<&InlineTable> =
+                a.iter().filter_map(Value::as_inline_table).collect();
+            // Columns are aligned to the nearest row which has all the
+            // same keys, or at least to the nearest row.
+            let mut nearest: Vec<&InlineTable> = rows.clone();
+            nearest.sort_by_key(|r| {
+                let j = rows.iter().position(|x| std::ptr::eq(*x, *r));
+                j.unwrap().abs_diff(i)
+            });
+            let neighbor = nearest
+                .iter()
+                .find(|r| slot.iter().all(|(k, _)| r.contains_key(k)))
+                .or(nearest.first())
+                .map(|r| (*r).clone());
+            let mut table = match neighbor {
+                None => {
+                    let mut table = InlineTable::new();
+                    for (k, v) in slot {
+                        table.insert(*k, v.clone());
+                    }
+                    table
+                }
+                Some(ref neighbor) => aligned(&rows, neighbor, slot),
+            };
+            // Only the layout, not the comments, of the neighbor's prefix.
+            let prefix = match a.iter().last() {
+                None => "\n    ".to_string(),
+                Some(v) => {
+                    let prefix = raw(v.decor().prefix());
+                    match prefix.rsplit_once('\n') {
+                        None => prefix,
+                        Some((_, indent)) => format!("\n{}", indent),
+                    }
+                }
+            };
+            table.decor_mut().set_prefix(prefix);
+            let non_last_suffix = match a.len() {
+                0 | 1 => String::new(),
+                _ => raw(a.get(0).unwrap().decor().suffix()),
+            };
+            if i == a.len() && i > 0 {
+                // Take over the layout before the closing bracket.
+                let last = a.get_mut(i - 1).unwrap();
+                let suffix = raw(last.decor().suffix());
+                last.decor_mut().set_suffix(non_last_suffix);
+                table.decor_mut().set_suffix(suffix);
+            } else {
+                table.decor_mut().set_suffix(non_last_suffix);
+            }
+            a.insert_formatted(i, Value::InlineTable(table));
+        }
+        Slots::Tables(ref mut a) => {
+            let mut table = Table::new();
+            let neighbor = a.get(i.unwrap_or(a.len()).saturating_sub(1));
+            for (k, v) in slot {
+                table.insert(k, toml_edit::value(v.clone()));
+                if let Some(decor) = neighbor.and_then(|n| n.key_decor(k)) {
+                    *table.key_decor_mut(k).unwrap() = decor.clone();
+                }
+            }
+            let mut tables = take_tables(a);
+            tables.insert(i.unwrap_or(tables.len()), table);
+            put_tables(a, tables);
+        }
+    }
+    doc.commit(&cfg_files)
+}
+
+/// Slot table with keys in the same order and with the same spacing as
+/// in the neighbor, adjusted for the differences in value widths, to keep
+/// the columns of the array aligned. Keys which the neighbor does not have
+/// are appended.
+fn aligned(
+    rows: &[&InlineTable],
+    neighbor: &InlineTable,
+    slot: &[(&str, Value)],
+) -> InlineTable {
+    let spaces = |n: i64| " ".repeat(n.max(0) as usize);
+    let width = |v: &Value| {
+        let mut v = v.clone();
+        v.decor_mut().clear();
+        v.to_string().chars().count() as i64
+    };
+    let mut table = InlineTable::new();
+    table.set_preamble(neighbor.preamble().as_str().unwrap_or_default());
+    let keys: Vec<&str> = neighbor
+        .iter()
+        .map(|(k, _)| k)
+        .filter(|k| slot.iter().any(|(sk, _)| sk == k))
+        .chain(
+            slot.iter()
+                .map(|(k, _)| *k)
+                .filter(|k| !neighbor.contains_key(k)),
+        )
+        .collect();
+    // Width difference, which is made up for before the next key.
+    let mut carry = 0;
+    for (i, &k) in keys.iter().enumerate() {
+        let is_last = i == keys.len() - 1;
+        let mut value =
+            slot.iter().find(|(sk, _)| *sk == k).unwrap().1.clone();
+        let mut key_prefix = match neighbor.key_decor(k) {
+            Some(decor) => raw(decor.prefix()),
+            None => " ".to_string(),
+        };
+        if i > 0 && neighbor.contains_key(k) {
+            let len = key_prefix.len() as i64;
+            key_prefix = spaces((len + carry).max(len.min(1)));
+        }
+        carry = 0;
+        match neighbor.get(k) {
+            Some(n_value) => {
+                *value.decor_mut() = n_value.decor().clone();
+                let diff = width(n_value) - width(&value);
+                let prefix = raw(n_value.decor().prefix()).len() as i64;
+                let suffix = raw(n_value.decor().suffix()).len() as i64;
+                // Right-aligned, as numbers often are.
+                let is_right = rows.iter().any(|r| {
+                    r.get(k).is_some_and(|v| raw(v.decor().prefix()).len() > 1)
+                });
+                if is_right {
+                    value
+                        .decor_mut()
+                        .set_prefix(spaces((prefix + diff).max(1)));
+                } else if suffix > 0 {
+                    let min = suffix.min(1);
+                    value
+                        .decor_mut()
+                        .set_suffix(spaces((suffix + diff).max(min)));
+                } else if !is_last {
+                    carry = diff;
+                }
+            }
+            None => {
+                value.decor_mut().set_prefix(" ");
+                value.decor_mut().set_suffix(if is_last { " " } else { "" });
+            }
+        }
+        table.insert(k, value);
+        table.key_decor_mut(k).unwrap().set_prefix(key_prefix);
+        if let Some(decor) = neighbor.key_decor(k) {
+            table
+                .key_decor_mut(k)
+                .unwrap()
+                .set_suffix(raw(decor.suffix()));
+        }
+    }
+    table
+}
+
+/// Removes the slot from the file which defines it.
+pub fn remove_slot(cfg_files: &[PathBuf], name: &str) -> Result<()> {
+    let mut doc = find_slot(cfg_files, name)?;
+    let mut slots = doc.slots_or_err()?;
+    let i = slots.position_or_err(name)?;
+    slots.remove(i);
+    doc.commit(cfg_files)
+}
+
+pub enum Anchor<'a> {
+    Before(&'a str),
+    After(&'a str),
+}
+
+/// Moves the slot within the file which defines it.
+/// The anchor slot has to be defined in the same file.
+pub fn move_slot(
+    cfg_files: &[PathBuf],
+    name: &str,
+    anchor: Anchor,
+) -> Result<()> {
+    let mut doc = find_slot(cfg_files, name)?;
+    let path = doc.path.clone();
+    let mut slots = doc.slots_or_err()?;
+    let from = slots.position_or_err(name)?;
+    let (other, offset) = match anchor {
+        Anchor::Before(other) => (other, 0),
+        Anchor::After(other) => (other, 1),
+    };
+    let to = slots.position(other).ok_or_else(|| {
+        anyhow!("Slot {:?} not found in the same file: {:?}", other, &path)
+    })?;
+    let to = to + offset;
+    // Index after the slot itself is taken out.
+    let to = if to > from { to - 1 } else { to };
+    slots.move_to(from, to);
+    doc.commit(cfg_files)
+}
+
+/// Sets a value at a dot-separated key path in the user's file, creating
+/// the tables on the way, if they are missing. A path through
+/// pista.slots.<name>, or slots.<name> for short, leads to the named slot,
+/// in whichever file it is defined. The value is parsed as TOML, and if
+/// that fails - taken as a string, so that quotes can be left out.
+pub fn set(cfg_files: &[PathBuf], key: &str, value: &str) -> Result<()> {
+    let path = super::overrides::path(key)?;
+    if !super::is_key(&path) {
+        bail!("Unknown config key: {:?}", key);
+    }
+    let value = value
+        .parse::<Value>()
+        .unwrap_or_else(|_| Value::from(value));
+    match &path[..] {
+        ["pista", "slots", name, rest @ ..] if !rest.is_empty() => {
+            let mut doc = find_slot(cfg_files, name)?;
+            doc.with_slot(name, |slot| set_in(slot, rest, value))
+                .unwrap()?;
+            doc.commit(cfg_files)
+        }
+        _ => {
+            let (user, cfg_files) = user_file(cfg_files)?;
+            let mut doc = Doc::open(&user)?;
+            set_in(doc.doc.as_table_mut(), &path, value)?;
+            doc.commit(&cfg_files)
+        }
+    }
+}
+
+fn set_in(
+    table: &mut dyn TableLike,
+    path: &[&str],
+    mut value: Value,
+) -> Result<()> {
+    match path {
+        [] => unreachable!(),
+        [key] => {
+            match table.get_mut(key) {
+                Some(Item::Value(old)) => {
+                    *value.decor_mut() = old.decor().clone();
+                    *old = value;
+                }
+                Some(_) => bail!("Not a value: {:?}", key),
+                None => insert_value(table, key, value),
+            }
+            Ok(())
+        }
+        [key, rest @ ..] => {
+            let sub = table
+                .entry(key)
+                .or_insert_with(toml_edit::table)
+                .as_table_like_mut()
+                .ok_or_else(|| anyhow!("Not a table: {:?}", key))?;
+            set_in(sub, rest, value)
+        }
+    }
+}
+
+/// Inserts a new key, taking over the spacing before the closing brace
+/// of an inline table, which would otherwise end up before the comma.
+/// The default is a trailing space, which looks just as out of place.
+fn insert_value(table: &mut dyn TableLike, key: &str, value: Value) {
+    let mut suffix = String::new();
+    if let Some((_, Item::Value(last))) = table.iter_mut().last() {
+        let last_suffix = raw(last.decor().suffix());
+        if last_suffix.chars().all(|c| c == ' ') {
+            suffix = last_suffix;
+            last.decor_mut().set_suffix("");
+        }
+    }
+    table.insert(key, Item::Value(value));
+    if let Some(v) = table.get_mut(key).and_then(Item::as_value_mut) {
+        v.decor_mut().set_suffix(suffix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml_edit::{Document, InlineTable, Value};
+
+    const INLINE: &str = r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+] # After.
+"#;
+
+    const TABLES: &str = r#"
+# The slots.
+[pista]
+
+# Clock.
+[[pista.slots]]
+name = "a"
+ttl = 1 # Often.
+cmd = "date"
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+
+# Last.
+[[pista.slots]]
+name = "c"
+ttl = 3
+cmd = "c"
+"#;
+
+    /// Result of the edit of the config.
+    fn edited(
+        cfg: &str,
+        edit: impl FnOnce(&[std::path::PathBuf]) -> anyhow::Result<()>,
+    ) -> String {
+        let (result, edited) = try_edit(cfg, edit);
+        result.unwrap();
+        edited
+    }
+
+    /// The config after the edit was rejected.
+    fn rejected(
+        cfg: &str,
+        edit: impl FnOnce(&[std::path::PathBuf]) -> anyhow::Result<()>,
+    ) -> String {
+        let (result, edited) = try_edit(cfg, edit);
+        assert!(result.is_err());
+        edited
+    }
+
+    fn try_edit(
+        cfg: &str,
+        edit: impl FnOnce(&[std::path::PathBuf]) -> anyhow::Result<()>,
+    ) -> (anyhow::Result<()>, String) {
+        let dir = std::env::temp_dir().join(format!(
+            "pistactl-test-edit-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, cfg).unwrap();
+        let result = edit(std::slice::from_ref(&path));
+        let edited = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (result, edited)
+    }
+
+    #[test]
+    fn add_slot() {
+        let slot = |name: &str| {
+            [
+                ("name", Value::from(name)),
+                ("ttl", Value::from(5)),
+                ("cmd", Value::from(name)),
+            ]
+        };
+        let add = |cfg, name, before| {
+            edited(cfg, |files| super::add_slot(files, &slot(name), before))
+        };
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+    {name = "d", ttl = 5, cmd = "d"},
+] # After.
+"#,
+            add(INLINE, "d", None)
+        );
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    {name = "d", ttl = 5, cmd = "d"},
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+] # After.
+"#,
+            add(INLINE, "d", Some("b"))
+        );
+        assert_eq!(
+            r#"
+# The slots.
+[pista]
+
+[[pista.slots]]
+name = "d"
+ttl = 5
+cmd = "d"
+
+# Clock.
+[[pista.slots]]
+name = "a"
+ttl = 1 # Often.
+cmd = "date"
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+
+# Last.
+[[pista.slots]]
+name = "c"
+ttl = 3
+cmd = "c"
+"#,
+            add(TABLES, "d", Some("a"))
+        );
+        assert_eq!(
+            INLINE,
+            rejected(INLINE, |files| super::add_slot(files, &slot("a"), None))
+        );
+        let invalid = [("name", Value::from("d")), ("ttl", Value::from(5))];
+        assert_eq!(
+            INLINE,
+            rejected(INLINE, |files| super::add_slot(files, &invalid, None))
+        );
+    }
+
+    #[test]
+    fn set() {
+        let set = |cfg, key, value| {
+            edited(cfg, |files| super::set(files, key, value))
+        };
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 5, cmd = "date"},
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+] # After.
+"#,
+            set(INLINE, "slots.a.ttl", "5")
+        );
+        assert_eq!(
+            r#"
+# The slots.
+[pista]
+separator = " | "
+
+# Clock.
+[[pista.slots]]
+name = "a"
+ttl = 5 # Often.
+cmd = "date"
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+
+# Last.
+[[pista.slots]]
+name = "c"
+ttl = 3
+cmd = "c"
+"#,
+            set(
+                &set(TABLES, "pista.slots.a.ttl", "5"),
+                "pista.separator",
+                " | "
+            )
+        );
+        for (key, value) in [
+            ("slots.a.ttl", "soon"),
+            ("slots.a.nope", "1"),
+            ("slots.nope.ttl", "1"),
+            ("pista.nope", "1"),
+            ("pista..ttl", "1"),
+        ] {
+            assert_eq!(
+                INLINE,
+                rejected(INLINE, |files| super::set(files, key, value))
+            );
+        }
+    }
+
+    #[test]
+    fn set_slot_enabled() {
+        let set_enabled = |cfg, enabled| {
+            edited(cfg, |files| super::set_slot_enabled(files, "b", enabled))
+        };
+        let disabled = set_enabled(INLINE, false);
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    {name = "b", ttl = 2, cmd = "b", enabled = false}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+] # After.
+"#,
+            disabled
+        );
+        assert_eq!(INLINE, set_enabled(&disabled, true));
+        assert_eq!(
+            INLINE,
+            rejected(INLINE, |files| {
+                super::set_slot_enabled(files, "nope", false)
+            })
+        );
+    }
+
+    #[test]
+    fn remove_slot() {
+        let remove =
+            |cfg, name| edited(cfg, |files| super::remove_slot(files, name));
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+] # After.
+"#,
+            remove(INLINE, "a")
+        );
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+] # After.
+"#,
+            remove(INLINE, "b")
+        );
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+] # After.
+"#,
+            remove(INLINE, "c")
+        );
+        assert_eq!(
+            r#"[pista]
+slots = [{name = "b", ttl = 1, cmd = "b"}, {name = "c", ttl = 1, cmd = "c"}]
+"#,
+            remove(
+                r#"[pista]
+slots = [{name = "a", ttl = 1, cmd = "a"}, {name = "b", ttl = 1, cmd = "b"}, {name = "c", ttl = 1, cmd = "c"}]
+"#,
+                "a"
+            )
+        );
+        assert_eq!(
+            r#"
+# The slots.
+[pista]
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+
+# Last.
+[[pista.slots]]
+name = "c"
+ttl = 3
+cmd = "c"
+"#,
+            remove(TABLES, "a")
+        );
+        assert_eq!(
+            r#"
+# The slots.
+[pista]
+
+# Clock.
+[[pista.slots]]
+name = "a"
+ttl = 1 # Often.
+cmd = "date"
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+"#,
+            remove(TABLES, "c")
+        );
+    }
+
+    #[test]
+    fn move_slot() {
+        use super::Anchor::{After, Before};
+
+        let move_slot = |cfg, name, anchor| {
+            edited(cfg, |files| super::move_slot(files, name, anchor))
+        };
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+] # After.
+"#,
+            move_slot(INLINE, "c", Before("a"))
+        );
+        assert_eq!(
+            r#"
+[pista]
+slots = [
+    {name = "b", ttl = 2, cmd = "b"}, # Trailing b.
+    # Last.
+    {name = "c", ttl = 3, cmd = "c"},
+    # Clock.
+    {name = "a", ttl = 1, cmd = "date"},
+] # After.
+"#,
+            move_slot(INLINE, "a", After("c"))
+        );
+        assert_eq!(
+            r#"[pista]
+slots = [
+  {name = "c", ttl = 1, cmd = "c"}, # C
+  {name = "a", ttl = 1, cmd = "a"} # A
+]
+"#,
+            move_slot(
+                r#"[pista]
+slots = [
+  {name = "a", ttl = 1, cmd = "a"}, # A
+  {name = "c", ttl = 1, cmd = "c"} # C
+]
+"#,
+                "c",
+                Before("a")
+            )
+        );
+        assert_eq!(
+            r#"
+# The slots.
+[pista]
+
+# Last.
+[[pista.slots]]
+name = "c"
+ttl = 3
+cmd = "c"
+
+# Clock.
+[[pista.slots]]
+name = "a"
+ttl = 1 # Often.
+cmd = "date"
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+"#,
+            move_slot(TABLES, "c", Before("a"))
+        );
+        assert_eq!(
+            r#"
+# The slots.
+[pista]
+
+[[pista.slots]]
+name = "b"
+ttl = 2
+cmd = "b"
+
+# Clock.
+[[pista.slots]]
+name = "a"
+ttl = 1 # Often.
+cmd = "date"
+
+# Last.
+[[pista.slots]]
+name = "c"
+ttl = 3
+cmd = "c"
+"#,
+            move_slot(TABLES, "a", After("b"))
+        );
+    }
+
+    #[test]
+    fn aligned() {
+        let doc: Document = r#"
+            slots = [
+                {name = "time",   len = 19, ttl =   1, cmd = "date"},
+                {name = "upower", len =  4, ttl = 120, cmd = "upower -d"},
+            ]
+            "#
+        .parse()
+        .unwrap();
+        let rows: Vec<&InlineTable> = doc["slots"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_inline_table)
+            .collect();
+        let slot = [
+            ("name", Value::from("vpn")),
+            ("len", Value::from(3)),
+            ("ttl", Value::from(30)),
+            ("cmd", Value::from("vpn-status")),
+        ];
+        assert_eq!(
+            r#"{name = "vpn",    len =  3, ttl =  30, cmd = "vpn-status"}"#,
+            super::aligned(&rows, rows[1], &slot).to_string()
+        );
+        let slot = [
+            ("name", Value::from("cpu")),
+            ("ttl", Value::from(5)),
+            ("cmd", Value::from("top")),
+            ("interpreter", Value::from("/bin/sh")),
+        ];
+        assert_eq!(
+            r#"{name = "cpu",  ttl =   5, cmd = "top", interpreter = "/bin/sh" }"#,
+            super::aligned(&rows, rows[0], &slot).to_string()
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Reading of config files together with the files they include.
+//!
+//! Merge order is that of the given files, each expanded depth-first:
+//! the including file comes first, then each
+//! of its include patterns, in the listed order, with the files matching
+//! one pattern sorted by path. Each next file is merged on top of the
+//! previous result:
+//! - tables are merged key by key;
+//! - pista.slots are appended, unless a slot with the same name already
+//!   exists, in which case it is replaced in its original position;
+//! - any other value is replaced.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use expanduser::expanduser;
+use toml::{Table, Value};
+
+pub fn load(paths: &[PathBuf]) -> Result<Table> {
+    let mut merged = Table::new();
+    for (_, table) in layers(paths)? {
+        merge(&mut merged, table);
+    }
+    Ok(merged)
+}
+
+/// The files and the ones they include, with their contents,
+/// in merge order.
+pub fn layers(paths: &[PathBuf]) -> Result<Vec<(PathBuf, Table)>> {
+    let mut layers = Vec::new();
+    let mut visited = HashSet::new();
+    for path in paths {
+        collect(&mut layers, path, &mut visited)?;
+    }
+    Ok(layers)
+}
+
+pub fn merge(base: &mut Table, top: Table) {
+    merge_tables(base, top, &[]);
+}
+
+/// File in effect for each key, in dotted form, according to the merge
+/// rules: each slot is a single key, pista.slots.<name>, since it is
+/// replaced as a whole, and a value replaces all the keys under it.
+pub fn origins(layers: &[(PathBuf, Table)]) -> BTreeMap<String, PathBuf> {
+    let mut origins: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for (path, table) in layers {
+        let mut keys = Vec::new();
+        flatten(table, "", &mut keys);
+        for key in keys {
+            origins.retain(|k, _| {
+                !(k.starts_with(&format!("{}.", key))
+                    || key.starts_with(&format!("{}.", k)))
+            });
+            origins.insert(key, path.clone());
+        }
+    }
+    origins
+}
+
+fn flatten(table: &Table, prefix: &str, keys: &mut Vec<String>) {
+    for (key, val) in table {
+        let key = format!("{}{}", prefix, key);
+        match val {
+            Value::Table(t) => flatten(t, &format!("{}.", key), keys),
+            Value::Array(slots) if key == "pista.slots" => {
+                for (i, slot) in slots.iter().enumerate() {
+                    let name = slot
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map_or_else(|| i.to_string(), String::from);
+                    keys.push(format!("{}.{}", key, name));
+                }
+            }
+            _ => keys.push(key),
+        }
+    }
+}
+
+fn collect(
+    layers: &mut Vec<(PathBuf, Table)>,
+    path: &Path,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    let canonical = std::fs::canonicalize(path)
+        .with_context(|| format!("Config file not found: {:?}", path))?;
+    if !visited.insert(canonical) {
+        bail!("Config file included more than once: {:?}", path);
+    }
+    let (table, file) = read(path)?;
+    let includes = includes(path, file.include.as_deref().unwrap_or(&[]))?;
+    layers.push((path.to_path_buf(), table));
+    for include in includes {
+        collect(layers, &include, visited)?;
+    }
+    Ok(())
+}
+
+/// Parses a single file and checks it against the config format,
+/// so that errors are attributed to the file that caused them.
+/// Deprecated keys are moved to their new ones, with a warning.
+fn read(path: &Path) -> Result<(Table, super::file::Cfg)> {
+    let data: String = crate::fs::read_to_string(path)?;
+    let mut table: Table = toml::from_str(&data)
+        .with_context(|| format!("Failed to parse TOML from: {:?}", path))?;
+    super::migrate::table(&mut table, path);
+    let mut checked = table.clone();
+    super::templates::fill_placeholders(&mut checked);
+    let file: super::file::Cfg = checked
+        .try_into()
+        .with_context(|| format!("Invalid config in: {:?}", path))?;
+    super::templates::validate(&file)
+        .with_context(|| format!("Invalid config in: {:?}", path))?;
+    super::migrate::check_version(file.version)
+        .with_context(|| format!("Unsupported config in: {:?}", path))?;
+    Ok((table, file))
+}
+
+fn includes(path: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let pattern = dir.join(expanduser(pattern)?);
+        let pattern = pattern.to_string_lossy();
+        let mut matches = glob::glob(&pattern)
+            .with_context(|| {
+                format!("Invalid include pattern in: {:?}", path)
+            })?
+            .collect::<std::result::Result<Vec<PathBuf>, _>>()?;
+        if matches.is_empty() && !is_glob(&pattern) {
+            bail!("Included file not found: {:?}, in: {:?}", pattern, path);
+        }
+        matches.sort();
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn merge_tables(base: &mut Table, top: Table, path: &[&str]) {
+    for (key, top_val) in top {
+        match (base.get_mut(&key), top_val) {
+            (Some(Value::Table(base_tab)), Value::Table(top_tab)) => {
+                merge_tables(base_tab, top_tab, &[path, &[&key]].concat());
+            }
+            (Some(Value::Array(base_arr)), Value::Array(top_arr))
+                if path == ["pista"] && key == "slots" =>
+            {
+                merge_slots(base_arr, top_arr);
+            }
+            (_, top_val) => {
+                base.insert(key, top_val);
+            }
+        }
+    }
+}
+
+fn merge_slots(base: &mut Vec<Value>, top: Vec<Value>) {
+    fn name(slot: &Value) -> Option<&str> {
+        slot.get("name").and_then(Value::as_str)
+    }
+    for slot in top {
+        let existing = name(&slot).and_then(|top_name| {
+            base.iter().position(|s| name(s) == Some(top_name))
+        });
+        match existing {
+            Some(i) => base[i] = slot,
+            None => base.push(slot),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use toml::Table;
+
+    #[test]
+    fn merge() {
+        let mut base: Table = toml::from_str(
+            r#"
+            session = "a"
+            [notifications]
+            log_lines_limit = 1
+            width_limit = 2
+            indent = ""
+            [pista]
+            separator = " "
+            slots = [
+                {name = "x", ttl = 1, cmd = "x1"},
+                {ttl = 1, cmd = "anonymous"},
+                {name = "y", ttl = 1, cmd = "y1"},
+            ]
+            "#,
+        )
+        .unwrap();
+        let top: Table = toml::from_str(
+            r#"
+            debug = true
+            [notifications]
+            log_lines_limit = 5
+            [pista]
+            slots = [
+                {name = "y", ttl = 2, cmd = "y2"},
+                {ttl = 2, cmd = "anonymous"},
+                {name = "z", ttl = 2, cmd = "z2"},
+            ]
+            "#,
+        )
+        .unwrap();
+        let expected: Table = toml::from_str(
+            r#"
+            session = "a"
+            debug = true
+            [notifications]
+            log_lines_limit = 5
+            width_limit = 2
+            indent = ""
+            [pista]
+            separator = " "
+            slots = [
+                {name = "x", ttl = 1, cmd = "x1"},
+                {ttl = 1, cmd = "anonymous"},
+                {name = "y", ttl = 2, cmd = "y2"},
+                {ttl = 2, cmd = "anonymous"},
+                {name = "z", ttl = 2, cmd = "z2"},
+            ]
+            "#,
+        )
+        .unwrap();
+        super::merge(&mut base, top);
+        assert_eq!(expected, base);
+    }
+
+    #[test]
+    fn origins() {
+        let layer = |path: &str, data: &str| {
+            (PathBuf::from(path), toml::from_str::<Table>(data).unwrap())
+        };
+        let layers = [
+            layer(
+                "a",
+                r#"
+                x = 1
+                [notifications]
+                indent = ""
+                [pista]
+                separator = " "
+                slots = [{name = "s", ttl = 1, cmd = "s"}]
+                "#,
+            ),
+            layer(
+                "b",
+                r#"
+                notifications = 0
+                [x]
+                y = 2
+                [pista]
+                slots = [{name = "t", ttl = 1, cmd = "t"}]
+                "#,
+            ),
+        ];
+        let origins = super::origins(&layers);
+        let origins: Vec<(&str, &str)> = origins
+            .iter()
+            .map(|(k, p)| (k.as_str(), p.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("notifications", "b"),
+                ("pista.separator", "a"),
+                ("pista.slots.s", "a"),
+                ("pista.slots.t", "b"),
+                ("x.y", "b"),
+            ],
+            origins
+        );
+    }
+}
@@ -0,0 +1,546 @@
+//! Changes of the config format between versions.
+//!
+//! A file declares the version of the format it is written in with the
+//! top-level version key. Files without it are of version 1. Files of
+//! a newer version than the supported one are rejected. Deprecated keys
+//! are still accepted, with a warning, regardless of the declared
+//! version, and config migrate rewrites them.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use toml_edit::{Decor, InlineTable, Item, Table, TableLike, Value};
+
+pub const VERSION: i64 = 2;
+
+const KEY_VERSION: &str = "version";
+
+/// Keys renamed, or moved, in version 2, from and to, in dotted form.
+/// A * stands for any key, the same one on both sides.
+const RENAMES: &[(&str, &str)] =
+    &[("notifications.urgency.*", "notifications.events.*.urgency")];
+
+pub fn check_version(version: Option<i64>) -> Result<()> {
+    match version {
+        Some(v) if v > VERSION => bail!(
+            "Config version {} is newer than the supported {}",
+            v,
+            VERSION
+        ),
+        Some(v) if v < 1 => {
+            bail!("Invalid config version {}. The first one is 1", v)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Moves the values of the deprecated keys of the file to their new ones.
+pub fn table(table: &mut toml::Table, path: &Path) {
+    for (from, to) in RENAMES {
+        let found = matching(from, |keys| {
+            toml_table(table, keys)
+                .map(|t| t.keys().cloned().collect())
+                .unwrap_or_default()
+        });
+        for keys in found {
+            let new = renamed(from, to, &keys);
+            tracing::warn!(
+                "Deprecated config key {:?} in: {:?}. Use {:?} instead, \
+                or run: {} config migrate",
+                keys.join("."),
+                path,
+                new.join("."),
+                crate::NAME!()
+            );
+            let (key, parent) = keys.split_last().unwrap();
+            let val = toml_table_mut(table, parent)
+                .and_then(|t| t.remove(key))
+                .unwrap();
+            if !toml_insert(table, &new, val) {
+                tracing::warn!(
+                    "Ignoring {:?}, since {:?} is also set.",
+                    keys.join("."),
+                    new.join(".")
+                );
+            }
+        }
+    }
+}
+
+/// Upgrades the document to the current version, preserving comments,
+/// formatting and the order of keys. Returns whether anything changed.
+pub fn document(doc: &mut toml_edit::Document) -> Result<bool> {
+    let root = doc.as_table_mut();
+    let version = root.get(KEY_VERSION).and_then(toml_edit::Item::as_integer);
+    check_version(version)?;
+    let mut moved = false;
+    for (from, to) in RENAMES {
+        let found = matching(from, |keys| {
+            table_like(root, keys)
+                .map(|t| t.iter().map(|(k, _)| k.to_string()).collect())
+                .unwrap_or_default()
+        });
+        for keys in &found {
+            move_key(root, keys, &renamed(from, to, keys), &found);
+            moved = true;
+        }
+    }
+    if version.unwrap_or(1) == VERSION && !moved {
+        return Ok(false);
+    }
+    set_version(root);
+    Ok(true)
+}
+
+/// Paths of the existing keys matching the pattern, given the keys of the
+/// table at a path, if there is one.
+fn matching(
+    pattern: &str,
+    keys: impl Fn(&[String]) -> Vec<String>,
+) -> Vec<Vec<String>> {
+    let mut paths = vec![Vec::new()];
+    for part in pattern.split('.') {
+        paths = paths
+            .into_iter()
+            .flat_map(|path| {
+                keys(&path)
+                    .into_iter()
+                    .filter(|k| part == "*" || k == part)
+                    .map(move |k| [path.clone(), vec![k]].concat())
+            })
+            .collect();
+    }
+    paths
+}
+
+/// Path of the new key of the one at the path matching from.
+fn renamed(from: &str, to: &str, path: &[String]) -> Vec<String> {
+    let mut any = from
+        .split('.')
+        .zip(path)
+        .filter(|(part, _)| *part == "*")
+        .map(|(_, key)| key.clone());
+    to.split('.')
+        .map(|part| match part {
+            "*" => any.next().unwrap_or_default(),
+            _ => part.to_string(),
+        })
+        .collect()
+}
+
+fn toml_table<'t>(
+    mut table: &'t toml::Table,
+    path: &[String],
+) -> Option<&'t toml::Table> {
+    for key in path {
+        table = table.get(key)?.as_table()?;
+    }
+    Some(table)
+}
+
+fn toml_table_mut<'t>(
+    mut table: &'t mut toml::Table,
+    path: &[String],
+) -> Option<&'t mut toml::Table> {
+    for key in path {
+        table = table.get_mut(key)?.as_table_mut()?;
+    }
+    Some(table)
+}
+
+/// Inserts the value, unless the key is already set, creating the tables
+/// on the way. Returns whether it was inserted.
+fn toml_insert(
+    mut table: &mut toml::Table,
+    path: &[String],
+    val: toml::Value,
+) -> bool {
+    let (key, parents) = path.split_last().unwrap();
+    for parent in parents {
+        let sub = table
+            .entry(parent.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        table = match sub.as_table_mut() {
+            Some(sub) => sub,
+            None => return false,
+        };
+    }
+    if table.contains_key(key) {
+        return false;
+    }
+    table.insert(key.clone(), val);
+    true
+}
+
+fn table_like<'t>(
+    root: &'t Table,
+    path: &[String],
+) -> Option<&'t dyn TableLike> {
+    let mut table: &dyn TableLike = root;
+    for key in path {
+        table = table.get(key)?.as_table_like()?;
+    }
+    Some(table)
+}
+
+fn table_like_mut<'t>(
+    root: &'t mut Table,
+    path: &[String],
+) -> Option<&'t mut dyn TableLike> {
+    let mut table: &mut dyn TableLike = root;
+    for key in path {
+        table = table.get_mut(key)?.as_table_like_mut()?;
+    }
+    Some(table)
+}
+
+/// Moves the key with its comments. Those above the table it is taken
+/// from, which all of the found keys are, go with the first of them.
+/// The emptied table is removed, and the missing ones on the way to the
+/// new key are created, of the same kind as their parent, in place of
+/// the removed one.
+fn move_key(
+    root: &mut Table,
+    from: &[String],
+    to: &[String],
+    found: &[Vec<String>],
+) {
+    let (key, parent) = from.split_last().unwrap();
+    let (header, position) = match parent.split_last() {
+        Some((name, grandparent)) => {
+            let all_found = table_like(root, parent).is_some_and(|t| {
+                t.iter().all(|(k, _)| {
+                    found.contains(&[parent, &[k.to_string()]].concat())
+                })
+            });
+            match table_like_mut(root, grandparent)
+                .and_then(|t| t.get_mut(name))
+                .and_then(Item::as_table_mut)
+            {
+                Some(t) if all_found => {
+                    let header = super::edit::raw(t.decor().prefix());
+                    t.decor_mut().set_prefix("");
+                    (header, t.position())
+                }
+                Some(t) => (String::new(), t.position()),
+                None => (String::new(), None),
+            }
+        }
+        None => (String::new(), None),
+    };
+    let inline = match parent.split_last() {
+        Some((name, grandparent)) => table_like(root, grandparent)
+            .and_then(|t| t.get(name))
+            .is_some_and(Item::is_inline_table),
+        None => false,
+    };
+    let table = table_like_mut(root, parent).unwrap();
+    let mut decor = table.key_decor(key).cloned().unwrap_or_default();
+    decor.set_prefix(header + &super::edit::raw(decor.prefix()));
+    let item = table.remove(key).unwrap();
+    if table.is_empty() {
+        if let Some((name, grandparent)) = parent.split_last() {
+            table_like_mut(root, grandparent).unwrap().remove(name);
+        }
+    }
+    if !insert(root, to, item, decor, inline, position) {
+        tracing::warn!(
+            "Dropping {:?}, since {:?} is also set.",
+            from.join("."),
+            to.join(".")
+        );
+    }
+}
+
+/// Inserts the item, unless the key is already set. The comments above
+/// it go above the table, if the table is created for it. The spacing
+/// is reset, if it is moved into an inline table, or out of one.
+fn insert(
+    root: &mut Table,
+    path: &[String],
+    mut item: Item,
+    mut decor: Decor,
+    was_inline: bool,
+    position: Option<usize>,
+) -> bool {
+    let (key, parents) = path.split_last().unwrap();
+    let mut table: &mut dyn TableLike = root;
+    let mut inline = false;
+    let mut created = false;
+    for parent in parents {
+        created = !table.contains_key(parent);
+        if created {
+            let sub = if inline {
+                Item::Value(Value::InlineTable(InlineTable::new()))
+            } else {
+                let mut sub = Table::new();
+                sub.set_implicit(true);
+                if let Some(position) = position {
+                    sub.set_position(position);
+                }
+                Item::Table(sub)
+            };
+            table.insert(parent, sub);
+        }
+        let sub = table.get_mut(parent).unwrap();
+        inline = sub.is_inline_table();
+        table = match sub.as_table_like_mut() {
+            Some(sub) => sub,
+            None => return false,
+        };
+    }
+    if table.contains_key(key) {
+        return false;
+    }
+    if inline != was_inline {
+        if let Some(v) = item.as_value_mut() {
+            v.decor_mut().clear();
+        }
+    }
+    if inline || inline != was_inline {
+        decor.clear();
+    }
+    table.insert(key, item);
+    if created && !inline {
+        let prefix = super::edit::raw(decor.prefix());
+        decor.set_prefix("");
+        let parent = parents.last().unwrap();
+        let (grandparent, _) = path.split_at(path.len() - 2);
+        if let Some(t) = table_like_mut(root, grandparent)
+            .and_then(|t| t.get_mut(parent))
+            .and_then(Item::as_table_mut)
+        {
+            t.decor_mut().set_prefix(prefix);
+        }
+    }
+    if let Some(d) = table_like_mut(root, &path[..path.len() - 1])
+        .and_then(|t| t.key_decor_mut(key))
+    {
+        *d = decor;
+    }
+    true
+}
+
+/// Sets the version key to the current one, first in the file.
+fn set_version(root: &mut toml_edit::Table) {
+    let existing: Vec<String> =
+        root.iter().map(|(k, _)| k.to_string()).collect();
+    // Keys can only be inserted at the end, so to keep the order,
+    // all of them are taken out and put back.
+    let mut entries = Vec::new();
+    for k in &existing {
+        if let Some(entry) = root.remove_entry(k) {
+            entries.push(entry);
+        }
+    }
+    let version_key =
+        match entries.iter().position(|(key, _)| key.get() == KEY_VERSION) {
+            Some(i) => entries.remove(i).0,
+            None => {
+                let mut key = toml_edit::Key::new(KEY_VERSION);
+                key.decor_mut().set_suffix(" ");
+                // Comment at the top of the file stays at the top,
+                // whether it is above a key, or, if there are only
+                // tables, above the first of them.
+                let has_values = entries.iter().any(|(_, i)| i.is_value());
+                let first = if has_values {
+                    entries
+                        .iter_mut()
+                        .find(|(_, item)| item.is_value())
+                        .map(|(first, _)| first.decor_mut())
+                } else {
+                    entries
+                        .iter_mut()
+                        .filter_map(|(_, item)| item.as_table_mut())
+                        .filter(|t| t.position().is_some())
+                        .min_by_key(|t| t.position())
+                        .map(|t| t.decor_mut())
+                };
+                if let Some(first) = first {
+                    key.decor_mut()
+                        .set_prefix(super::edit::raw(first.prefix()));
+                    // A blank line between the key and the table.
+                    first.set_prefix(if has_values { "" } else { "\n" });
+                }
+                key
+            }
+        };
+    root.insert_formatted(&version_key, toml_edit::value(VERSION));
+    for (key, item) in entries {
+        root.insert_formatted(&key, item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_version() {
+        assert!(super::check_version(None).is_ok());
+        assert!(super::check_version(Some(1)).is_ok());
+        assert!(super::check_version(Some(super::VERSION)).is_ok());
+        assert!(super::check_version(Some(super::VERSION + 1)).is_err());
+        assert!(super::check_version(Some(0)).is_err());
+        assert!(super::check_version(Some(-1)).is_err());
+    }
+
+    #[test]
+    fn table() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            [notifications]
+            indent = ""
+            urgency = {slot-exited = "normal", pista-exited = "low"}
+            [notifications.events.pista-exited]
+            urgency = "critical"
+            "#,
+        )
+        .unwrap();
+        super::table(&mut table, std::path::Path::new("config.toml"));
+        let expected: toml::Table = toml::from_str(
+            r#"
+            [notifications]
+            indent = ""
+            urgency = {}
+            [notifications.events.slot-exited]
+            urgency = "normal"
+            [notifications.events.pista-exited]
+            urgency = "critical"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(expected, table);
+    }
+
+    #[test]
+    fn document() {
+        let cfg = format!(
+            r#"# Mine
+version = {}
+debug = true
+
+[pista]
+separator = " "
+"#,
+            super::VERSION
+        );
+        let mut doc: toml_edit::Document = cfg.parse().unwrap();
+        assert!(!super::document(&mut doc).unwrap());
+        assert_eq!(cfg, doc.to_string());
+        for version in [super::VERSION + 1, 0] {
+            let mut doc: toml_edit::Document =
+                format!("version = {}\n", version).parse().unwrap();
+            assert!(super::document(&mut doc).is_err());
+        }
+    }
+
+    #[test]
+    fn document_renames() {
+        let mut doc: toml_edit::Document = r#"# Mine
+debug = true
+
+[notifications]
+indent = "  "
+
+# Quieter.
+[notifications.urgency]
+slot-exited = "normal" # Often.
+# Never mind pista.
+pista-exited = "low"
+
+[pista]
+separator = " "
+"#
+        .parse()
+        .unwrap();
+        assert!(super::document(&mut doc).unwrap());
+        assert_eq!(
+            format!(
+                r#"# Mine
+version = {}
+debug = true
+
+[notifications]
+indent = "  "
+
+# Quieter.
+[notifications.events.slot-exited]
+urgency = "normal" # Often.
+# Never mind pista.
+[notifications.events.pista-exited]
+urgency = "low"
+
+[pista]
+separator = " "
+"#,
+                super::VERSION
+            ),
+            doc.to_string()
+        );
+        assert!(!super::document(&mut doc).unwrap());
+
+        let mut doc: toml_edit::Document = r#"[notifications]
+urgency = {slot-exited = "normal"}
+events = {slot-exited = {summary = "x"}, pista-exited = {}}
+"#
+        .parse()
+        .unwrap();
+        assert!(super::document(&mut doc).unwrap());
+        assert_eq!(
+            format!(
+                r#"version = {}
+
+[notifications]
+events = {{slot-exited = {{summary = "x", urgency = "normal"}}, pista-exited = {{}}}}
+"#,
+                super::VERSION
+            ),
+            doc.to_string()
+        );
+    }
+
+    #[test]
+    fn set_version() {
+        let mut doc: toml_edit::Document = r#"# Mine
+debug = true
+
+[pista]
+separator = " "
+"#
+        .parse()
+        .unwrap();
+        super::set_version(doc.as_table_mut());
+        assert_eq!(
+            format!(
+                r#"# Mine
+version = {}
+debug = true
+
+[pista]
+separator = " "
+"#,
+                super::VERSION
+            ),
+            doc.to_string()
+        );
+
+        let mut doc: toml_edit::Document = r#"# Mine
+[pista]
+separator = " "
+"#
+        .parse()
+        .unwrap();
+        super::set_version(doc.as_table_mut());
+        assert_eq!(
+            format!(
+                r#"# Mine
+version = {}
+
+[pista]
+separator = " "
+"#,
+                super::VERSION
+            ),
+            doc.to_string()
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Values given outside of config files, which override the ones in them:
+//!
+//! - environment variables: PISTACTL_PISTA__INTERVAL=2, where the part
+//!   after the prefix is the lower-cased key, with __ separating levels;
+//! - command line: --set pista.interval=2, applied after the environment.
+//!
+//! slots.<name>.<field> is a shortcut for pista.slots.<name>.<field>, which
+//! leads to the field of the named slot. Values are parsed as TOML, and if
+//! that fails - taken as strings, so that quotes can be left out.
+
+use anyhow::{anyhow, bail, Result};
+use toml::{Table, Value};
+
+const ENV_PREFIX: &str = concat!(crate::NAME!(), "_");
+
+pub type Override = (String, String);
+
+/// From the PISTACTL_* environment variables, sorted by name.
+pub fn from_env() -> Vec<Override> {
+    let prefix = ENV_PREFIX.to_uppercase();
+    let mut overrides: Vec<Override> = std::env::vars()
+        .filter_map(|(name, val)| {
+            let key = name.strip_prefix(&prefix)?;
+            Some((key.to_lowercase().replace("__", "."), val))
+        })
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+/// From a key=value string.
+pub fn parse(s: &str) -> Result<Override> {
+    let (key, val) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected key=value, got: {:?}", s))?;
+    Ok((key.trim().to_string(), val.to_string()))
+}
+
+pub fn apply(cfg: &mut Table, overrides: &[Override]) -> Result<()> {
+    for (key, val) in overrides {
+        tracing::debug!("Overriding config: {:?} = {:?}", key, val);
+        set(cfg, &path(key)?, parse_value(val))
+            .map_err(|e| e.context(format!("Invalid override: {:?}", key)))?;
+    }
+    Ok(())
+}
+
+/// Levels of the dot-separated key, with the slots.<name> shortcut
+/// expanded.
+pub fn path(key: &str) -> Result<Vec<&str>> {
+    let path: Vec<&str> = key.split('.').collect();
+    if path.iter().any(|k| k.is_empty()) {
+        bail!("Invalid config key: {:?}", key);
+    }
+    Ok(match &path[..] {
+        ["slots", rest @ ..] => [&["pista", "slots"], rest].concat(),
+        _ => path,
+    })
+}
+
+fn parse_value(s: &str) -> Value {
+    format!("v = {}", s)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(s.to_string()))
+}
+
+fn set(table: &mut Table, path: &[&str], val: Value) -> Result<()> {
+    match path {
+        [] => unreachable!(),
+        [key] => {
+            table.insert(key.to_string(), val);
+            Ok(())
+        }
+        ["pista", "slots", name, rest @ ..] if !rest.is_empty() => {
+            let slot = table
+                .get_mut("pista")
+                .and_then(|p| p.get_mut("slots"))
+                .and_then(Value::as_array_mut)
+                .and_then(|slots| {
+                    slots.iter_mut().find(|s| {
+                        s.get("name").and_then(Value::as_str) == Some(name)
+                    })
+                })
+                .and_then(Value::as_table_mut)
+                .ok_or_else(|| anyhow!("Slot not found: {:?}", name))?;
+            set(slot, rest, val)
+        }
+        [key, rest @ ..] => {
+            let sub = table
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("Not a table: {:?}", key))?;
+            set(sub, rest, val)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Table;
+
+    #[test]
+    fn apply() {
+        let mut cfg: Table = toml::from_str(
+            r#"
+            [pista]
+            x11 = true
+            slots = [{name = "time", ttl = 1, cmd = "date"}]
+            "#,
+        )
+        .unwrap();
+        let overrides = [
+            "pista.x11=false",
+            "slots.time.ttl=5",
+            "pista.separator= | ",
+            "notifications.indent=\"  \"",
+        ]
+        .map(|s| super::parse(s).unwrap());
+        super::apply(&mut cfg, &overrides).unwrap();
+        let expected: Table = toml::from_str(
+            r#"
+            [notifications]
+            indent = "  "
+            [pista]
+            x11 = false
+            separator = " | "
+            slots = [{name = "time", ttl = 5, cmd = "date"}]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(expected, cfg);
+        let unknown = [super::parse("slots.nope.ttl=1").unwrap()];
+        assert!(super::apply(&mut cfg, &unknown).is_err());
+        assert!(super::parse("pista.x11").is_err());
+    }
+}
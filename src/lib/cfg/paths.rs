@@ -0,0 +1,92 @@
+//! Where config files are looked for, when none is given on command line:
+//! - system-wide: pistactl/config.toml in each of $XDG_CONFIG_DIRS,
+//!   which defaults to /etc/xdg;
+//! - user's: $XDG_CONFIG_HOME/pistactl/config.toml, where $XDG_CONFIG_HOME
+//!   defaults to ~/.config, or, if it does not exist, the legacy
+//!   ~/.pistactl.toml.
+//!
+//! All found system-wide files are used, the first listed in
+//! $XDG_CONFIG_DIRS being the most important, and the user's file is
+//! layered on top of them.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use expanduser::expanduser;
+
+const FILE_NAME: &str = "config.toml";
+
+/// All the files looked for, whether they exist or not, in merge order.
+pub fn candidates() -> Result<Vec<PathBuf>> {
+    let mut paths = system();
+    paths.push(user()?);
+    paths.push(legacy()?);
+    Ok(paths)
+}
+
+/// The system-wide files looked for, in merge order.
+pub fn system() -> Vec<PathBuf> {
+    let dirs = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    let mut paths: Vec<PathBuf> = std::env::split_paths(&dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(in_dir)
+        .collect();
+    paths.reverse();
+    paths
+}
+
+/// The files to merge, in merge order.
+pub fn discover() -> Result<Vec<PathBuf>> {
+    let candidates = candidates()?;
+    let (user, legacy) = (user()?, legacy()?);
+    if user.exists() && legacy.exists() {
+        tracing::warn!(
+            "Legacy config file ignored: {:?}, in favor of: {:?}",
+            &legacy,
+            &user
+        );
+    }
+    let found: Vec<PathBuf> = candidates
+        .iter()
+        .filter(|path| path.exists())
+        .filter(|path| !(**path == legacy && user.exists()))
+        .cloned()
+        .collect();
+    if found.is_empty() {
+        bail!("Config file not found. Looked for: {:?}", candidates);
+    }
+    Ok(found)
+}
+
+/// The files and the ones they include, in merge order.
+pub fn layers(files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let layers = super::load::layers(files)?;
+    Ok(layers.into_iter().map(|(path, _)| path).collect())
+}
+
+/// File in effect for each key.
+pub fn origins(files: &[PathBuf]) -> Result<BTreeMap<String, PathBuf>> {
+    Ok(super::load::origins(&super::load::layers(files)?))
+}
+
+fn in_dir(dir: PathBuf) -> PathBuf {
+    dir.join(crate::NAME!()).join(FILE_NAME)
+}
+
+pub fn user() -> Result<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+        _ => expanduser("~/.config")?,
+    };
+    Ok(in_dir(dir))
+}
+
+fn legacy() -> Result<PathBuf> {
+    Ok(expanduser(concat!("~/.", crate::NAME!(), ".toml"))?)
+}
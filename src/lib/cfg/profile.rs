@@ -0,0 +1,141 @@
+//! Named variations of the config, in [profiles.<name>] sections:
+//!
+//! ```toml
+//! [profiles.work]
+//! exclude = ["upower"]              # Drop these slots,
+//! select = ["vpn", "wifi", "time"]  # or keep only these, in this order.
+//! pista = {separator = " | ", slots = [{name = "vpn", ...}]}
+//! ```
+//!
+//! The profile's pista table is merged on top of the main one, just like
+//! an included file would be, so slots are added or overridden by name.
+
+use anyhow::{anyhow, Context, Result};
+use toml::{Table, Value};
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct Profile {
+    /// Names of slots to keep, in the given order.
+    pub select: Option<Vec<String>>,
+
+    /// Names of slots to drop.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Merged on top of the [pista] section.
+    #[schemars(with = "Option<super::Pista>")]
+    pub pista: Option<Table>,
+}
+
+pub fn apply(cfg: &mut Table, name: &str) -> Result<()> {
+    let profiles = cfg
+        .get("profiles")
+        .and_then(Value::as_table)
+        .cloned()
+        .unwrap_or_default();
+    let profile: Profile = profiles
+        .get(name)
+        .ok_or_else(|| {
+            anyhow!(
+                "Profile not found: {:?}. Available: {:?}",
+                name,
+                profiles.keys().collect::<Vec<&String>>()
+            )
+        })?
+        .clone()
+        .try_into()
+        .with_context(|| format!("Invalid profile: {:?}", name))?;
+    if let Some(pista) = profile.pista {
+        let mut overlay = Table::new();
+        overlay.insert("pista".to_string(), Value::Table(pista));
+        super::load::merge(cfg, overlay);
+    }
+    let slots = match cfg
+        .get_mut("pista")
+        .and_then(|pista| pista.get_mut("slots"))
+        .and_then(Value::as_array_mut)
+    {
+        None => return Ok(()),
+        Some(slots) => slots,
+    };
+    let slot_name = |slot: &Value| {
+        slot.get("name").and_then(Value::as_str).map(String::from)
+    };
+    for excluded in &profile.exclude {
+        if !slots
+            .iter()
+            .any(|s| slot_name(s).as_ref() == Some(excluded))
+        {
+            tracing::warn!(
+                "Slot excluded by profile {:?} not found: {:?}",
+                name,
+                excluded
+            );
+        }
+    }
+    slots.retain(|s| match slot_name(s) {
+        None => true,
+        Some(ref n) => !profile.exclude.contains(n),
+    });
+    if let Some(select) = profile.select {
+        let mut selected = Vec::new();
+        for selected_name in select {
+            match slots
+                .iter()
+                .find(|s| slot_name(s).as_ref() == Some(&selected_name))
+            {
+                Some(slot) => selected.push(slot.clone()),
+                None => tracing::warn!(
+                    "Slot selected by profile {:?} not found: {:?}",
+                    name,
+                    selected_name
+                ),
+            }
+        }
+        *slots = selected;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Table;
+
+    #[test]
+    fn apply() {
+        let mut cfg: Table = toml::from_str(
+            r#"
+            [pista]
+            separator = " "
+            slots = [
+                {name = "a", ttl = 1, cmd = "a"},
+                {name = "b", ttl = 1, cmd = "b"},
+                {name = "c", ttl = 1, cmd = "c"},
+            ]
+            [profiles.p1]
+            exclude = ["b"]
+            pista = {separator = "|", slots = [{name = "c", ttl = 2, cmd = "c"}]}
+            [profiles.p2]
+            select = ["c", "a"]
+            "#,
+        )
+        .unwrap();
+        let names = |cfg: &Table| -> Vec<String> {
+            cfg["pista"]["slots"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|s| s["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let mut p1 = cfg.clone();
+        super::apply(&mut p1, "p1").unwrap();
+        assert_eq!(vec!["a", "c"], names(&p1));
+        assert_eq!(Some(2), p1["pista"]["slots"][1]["ttl"].as_integer());
+        assert_eq!(Some("|"), p1["pista"]["separator"].as_str());
+        let mut p2 = cfg.clone();
+        super::apply(&mut p2, "p2").unwrap();
+        assert_eq!(vec!["c", "a"], names(&p2));
+        assert!(super::apply(&mut cfg, "p3").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Fields shared by slots:
+//!
+//! ```toml
+//! [slot_defaults]
+//! ttl = 5
+//!
+//! [slot_templates.feed]
+//! interpreter = "/usr/bin/python3"
+//! env = {PYTHONUNBUFFERED = "1"}
+//!
+//! [[pista.slots]]
+//! name = "mail"
+//! extends = "feed"
+//! cmd = "..."
+//! ```
+//!
+//! Each field of a slot is resolved in order: the slot itself, its
+//! template, the template's template, if it extends one, the defaults,
+//! and finally the built-in defaults. Tables, like env, are merged key by
+//! key, the same way as included files are.
+
+use anyhow::{anyhow, bail, Context, Result};
+use toml::{Table, Value};
+
+const KEY_DEFAULTS: &str = "slot_defaults";
+const KEY_TEMPLATES: &str = "slot_templates";
+const KEY_EXTENDS: &str = "extends";
+
+/// Slot with all fields optional, as in defaults and templates.
+#[derive(Debug, serde::Deserialize)]
+#[serde(transparent)]
+pub struct PartialSlot(Table);
+
+impl PartialSlot {
+    /// Whether the fields it has are valid fields of a slot.
+    fn validate(&self) -> Result<()> {
+        let mut slot = self.0.clone();
+        fill_required(&mut slot);
+        Value::Table(slot).try_into::<super::Slot>()?;
+        Ok(())
+    }
+}
+
+impl schemars::JsonSchema for PartialSlot {
+    fn schema_name() -> String {
+        "PartialSlot".to_string()
+    }
+
+    fn json_schema(
+        gen: &mut schemars::gen::SchemaGenerator,
+    ) -> schemars::schema::Schema {
+        let mut schema =
+            <super::Slot as schemars::JsonSchema>::json_schema(gen);
+        if let schemars::schema::Schema::Object(ref mut obj) = schema {
+            obj.object().required.clear();
+            obj.metadata().description =
+                Some("Slot fields, all optional.".to_string());
+        }
+        schema
+    }
+}
+
+/// Checks the slot defaults and templates of the config.
+pub fn validate(cfg: &super::file::Cfg) -> Result<()> {
+    if let Some(ref defaults) = cfg.slot_defaults {
+        defaults
+            .validate()
+            .with_context(|| format!("Invalid {}", KEY_DEFAULTS))?;
+    }
+    for (name, template) in cfg.slot_templates.iter().flatten() {
+        template
+            .validate()
+            .with_context(|| format!("Invalid slot template: {:?}", name))?;
+    }
+    Ok(())
+}
+
+/// Puts placeholders in place of missing required fields of the slots,
+/// so that a file can be checked on its own, even though its slots get
+/// the missing fields from defaults or templates, possibly in another file.
+/// Whether they do, is checked after all files are merged.
+pub fn fill_placeholders(cfg: &mut Table) {
+    if let Some(slots) = slots_mut(cfg) {
+        for slot in slots.iter_mut().filter_map(Value::as_table_mut) {
+            fill_required(slot);
+        }
+    }
+}
+
+fn fill_required(slot: &mut Table) {
+    for (key, placeholder) in [
+        ("ttl", Value::Integer(0)),
+        ("cmd", Value::String(String::new())),
+    ] {
+        slot.entry(key).or_insert(placeholder);
+    }
+}
+
+pub fn resolve(cfg: &mut Table) -> Result<()> {
+    let defaults = match cfg.get(KEY_DEFAULTS) {
+        None => Table::new(),
+        Some(Value::Table(t)) => t.clone(),
+        Some(_) => bail!("Not a table: {:?}", KEY_DEFAULTS),
+    };
+    let templates = match cfg.get(KEY_TEMPLATES) {
+        None => Table::new(),
+        Some(Value::Table(t)) => t.clone(),
+        Some(_) => bail!("Not a table: {:?}", KEY_TEMPLATES),
+    };
+    let slots = match slots_mut(cfg) {
+        None => return Ok(()),
+        Some(slots) => slots,
+    };
+    for (position, slot) in (1..).zip(slots.iter_mut()) {
+        // Left for deserialization to report.
+        let Some(table) = slot.as_table() else {
+            continue;
+        };
+        let chain = chain(&templates, table).with_context(|| {
+            match table.get("name").and_then(Value::as_str) {
+                Some(name) => format!("Invalid slot: {:?}", name),
+                None => format!("Invalid slot at position: {}", position),
+            }
+        })?;
+        let mut resolved = defaults.clone();
+        for template in chain {
+            super::load::merge(&mut resolved, template);
+        }
+        super::load::merge(&mut resolved, table.clone());
+        *slot = Value::Table(resolved);
+    }
+    Ok(())
+}
+
+/// Templates the slot extends, from the most basic one.
+fn chain(templates: &Table, slot: &Table) -> Result<Vec<Table>> {
+    let mut chain: Vec<Table> = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+    let mut extends = slot.get(KEY_EXTENDS);
+    while let Some(name) = extends {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("Not a string: {:?}", KEY_EXTENDS))?;
+        if names.contains(&name) {
+            bail!("Slot templates extend each other: {:?}", names);
+        }
+        names.push(name);
+        let template = templates
+            .get(name)
+            .and_then(Value::as_table)
+            .ok_or_else(|| anyhow!("Slot template not found: {:?}", name))?;
+        chain.push(template.clone());
+        extends = template.get(KEY_EXTENDS);
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+fn slots_mut(cfg: &mut Table) -> Option<&mut Vec<Value>> {
+    cfg.get_mut("pista")
+        .and_then(|pista| pista.get_mut("slots"))
+        .and_then(Value::as_array_mut)
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Table;
+
+    #[test]
+    fn resolve() {
+        let mut cfg: Table = toml::from_str(
+            r#"
+            [slot_defaults]
+            ttl = 5
+            env = {A = "default", B = "default"}
+            [slot_templates.base]
+            interpreter = "/bin/sh"
+            [slot_templates.feed]
+            extends = "base"
+            env = {B = "feed"}
+            [pista]
+            slots = [
+                {name = "a", cmd = "a", extends = "feed", env = {C = "a"}},
+                {name = "b", cmd = "b", ttl = 1},
+            ]
+            "#,
+        )
+        .unwrap();
+        super::resolve(&mut cfg).unwrap();
+        let expected: Table = toml::from_str(
+            r#"
+            name = "a"
+            cmd = "a"
+            ttl = 5
+            interpreter = "/bin/sh"
+            extends = "feed"
+            env = {A = "default", B = "feed", C = "a"}
+            "#,
+        )
+        .unwrap();
+        assert_eq!(&expected, cfg["pista"]["slots"][0].as_table().unwrap());
+        assert_eq!(Some(1), cfg["pista"]["slots"][1]["ttl"].as_integer());
+
+        let mut cycle: Table = toml::from_str(
+            r#"
+            slot_templates = {x = {extends = "y"}, y = {extends = "x"}}
+            pista = {slots = [{ttl = 1, cmd = "z", extends = "x"}]}
+            "#,
+        )
+        .unwrap();
+        assert!(super::resolve(&mut cycle).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let validate = |cfg: &str| {
+            let file: super::super::file::Cfg = toml::from_str(cfg).unwrap();
+            super::validate(&file)
+        };
+        assert!(validate("slot_defaults = {ttl = 5}").is_ok());
+        assert!(validate("slot_defaults = {ttl = \"5\"}").is_err());
+        assert!(validate("slot_templates.a = {interpreter = \"sh\"}").is_ok());
+        assert!(validate("slot_templates.a = {env = 1}").is_err());
+    }
+}
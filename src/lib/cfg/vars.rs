@@ -0,0 +1,91 @@
+//! Expansion of {name} placeholders.
+//!
+//! A placeholder is a dot-separated path of identifiers in braces, which
+//! the lookup knows. Braces which do not enclose such a path are left
+//! alone, and so are the ones preceded by a $, or enclosing a name the
+//! lookup does not know, so that shell constructs like ${HOME}, {a,b} and
+//! awk '{print}' pass through unchanged. To pass a placeholder through
+//! literally, double its braces: {{name}} becomes {name}.
+
+use anyhow::Result;
+
+pub fn expand<F>(template: &str, mut lookup: F) -> Result<String>
+where
+    F: FnMut(&str) -> Result<Option<String>>,
+{
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find('{') {
+        let (before, from_brace) = rest.split_at(i);
+        expanded.push_str(before);
+        if let Some((name, after)) = from_brace
+            .strip_prefix("{{")
+            .and_then(|s| s.split_once("}}"))
+            .filter(|(name, _)| is_path(name))
+        {
+            expanded.push_str(&format!("{{{}}}", name));
+            rest = after;
+            continue;
+        }
+        let placeholder = from_brace
+            .strip_prefix('{')
+            .and_then(|s| s.split_once('}'))
+            .filter(|(name, _)| is_path(name) && !before.ends_with('$'));
+        let val = match placeholder {
+            Some((name, after)) => lookup(name)?.map(|val| (val, after)),
+            None => None,
+        };
+        match val {
+            Some((val, after)) => {
+                expanded.push_str(&val);
+                rest = after;
+            }
+            None => {
+                expanded.push('{');
+                rest = &from_brace[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn is_path(s: &str) -> bool {
+    s.split('.').all(|ident| {
+        let mut chars = ident.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    fn lookup(name: &str) -> Result<Option<String>> {
+        Ok(match name {
+            "a" => Some("A".to_string()),
+            "slot.name" => Some("x".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn expand() {
+        let expand = |s| super::expand(s, lookup).unwrap();
+        assert_eq!("A", expand("{a}"));
+        assert_eq!("-A-x-", expand("-{a}-{slot.name}-"));
+        assert_eq!("{a}", expand("{{a}}"));
+        assert_eq!("${a}", expand("${a}"));
+        assert_eq!("awk '{print $1}'", expand("awk '{print $1}'"));
+        assert_eq!("{1..3} {a,b} {a..b}", expand("{1..3} {a,b} {a..b}"));
+        assert_eq!("{ }", expand("{ }"));
+        assert_eq!("}{", expand("}{"));
+        assert_eq!("{if (x) {print}}", expand("{if (x) {{print}}}"));
+        assert_eq!("{b}", expand("{b}"));
+        assert_eq!("echo x | awk '{print}'", expand("echo x | awk '{print}'"));
+        assert!(super::expand("{a}", |_| anyhow::bail!("x")).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use expanduser::expanduser;
+
+/// Conditions for starting a slot. All which are given must hold.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub struct When {
+    /// Glob patterns, one of which must match the hostname.
+    pub hostname: Option<Vec<String>>,
+
+    /// Paths which must exist.
+    #[serde(default)]
+    pub exists: Vec<PathBuf>,
+
+    /// Programs which must be found in PATH.
+    #[serde(default)]
+    pub executable: Vec<String>,
+
+    /// Environment variables which must be set.
+    #[serde(default)]
+    pub env: Vec<String>,
+
+    /// Shell command which must exit with 0.
+    pub test: Option<String>,
+}
+
+impl When {
+    /// Reason for the first condition which does not hold, if any.
+    pub fn unmet(&self) -> Result<Option<String>> {
+        if let Some(ref patterns) = self.hostname {
+            let hostname = crate::process::hostname()?;
+            let mut matched = false;
+            for pattern in patterns {
+                let pattern =
+                    glob::Pattern::new(pattern).with_context(|| {
+                        format!("Invalid hostname pattern: {:?}", pattern)
+                    })?;
+                matched |= pattern.matches(&hostname);
+            }
+            if !matched {
+                return Ok(Some(format!(
+                    "hostname {:?} does not match any of {:?}",
+                    hostname, patterns
+                )));
+            }
+        }
+        for path in &self.exists {
+            let path = expanduser(path.to_string_lossy())?;
+            if !path.exists() {
+                return Ok(Some(format!("path does not exist: {:?}", path)));
+            }
+        }
+        for program in &self.executable {
+            if !is_in_path(program) {
+                return Ok(Some(format!(
+                    "executable not found: {:?}",
+                    program
+                )));
+            }
+        }
+        for var in &self.env {
+            if std::env::var_os(var).is_none() {
+                return Ok(Some(format!("env var not set: {:?}", var)));
+            }
+        }
+        if let Some(ref test) = self.test {
+            if !crate::process::test("sh", &["-c", test])? {
+                return Ok(Some(format!("test failed: {:?}", test)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn is_in_path(program: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    let is_executable = |path: PathBuf| {
+        path.metadata()
+            .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        return is_executable(PathBuf::from(program));
+    }
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths)
+            .any(|dir| is_executable(dir.join(program)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::When;
+
+    #[test]
+    fn hostname() {
+        let hostname = crate::process::hostname().unwrap();
+        assert!(!hostname.is_empty());
+        let when = |pattern: &str| When {
+            hostname: Some(vec!["x-*".to_string(), pattern.to_string()]),
+            ..When::default()
+        };
+        assert_eq!(None, when(&hostname).unmet().unwrap());
+        assert_eq!(None, when("*").unmet().unwrap());
+        assert!(when(&format!("{}-not", hostname))
+            .unmet()
+            .unwrap()
+            .is_some());
+        assert!(when("[").unmet().is_err());
+    }
+
+    #[test]
+    fn env() {
+        let when = |var: &str| When {
+            env: vec!["PATH".to_string(), var.to_string()],
+            ..When::default()
+        };
+        assert_eq!(None, when("PATH").unmet().unwrap());
+        assert!(when("PISTACTL_TEST_UNSET").unmet().unwrap().is_some());
+    }
+
+    #[test]
+    fn exists() {
+        let when = |path: std::path::PathBuf| When {
+            exists: vec![std::env::temp_dir(), path],
+            ..When::default()
+        };
+        assert_eq!(None, when(std::env::temp_dir()).unmet().unwrap());
+        let missing = std::env::temp_dir().join("pistactl-test-missing");
+        assert!(when(missing).unmet().unwrap().is_some());
+    }
+}
@@ -6,20 +6,26 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 use crate::{
+    alert,
     cfg::{self, Cfg},
-    process, scripts,
+    logs, notify, process, scripts,
+    state::{self, State},
+    timestamp,
     tmux::{self, Tmux},
 };
 
 const PERM_OWNER_RWX: u32 = 0o100 + 0o200 + 0o400;
 const NAME_CMD: &str = "cmd";
-const NAME_RUN: &str = "run";
-const NAME_OUT: &str = "out";
-const NAME_ERR: &str = "err";
-const NAME_PISTA: &str = "pista";
+const NAME_EXITED: &str = "exited";
+pub(crate) const NAME_RUN: &str = "run";
+pub(crate) const NAME_OUT: &str = "out";
+pub(crate) const NAME_ERR: &str = "err";
+pub(crate) const NAME_PISTA: &str = "pista";
 
 pub fn status(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
     let dir = &cfg.slots_fifos_dir;
@@ -30,20 +36,50 @@ pub fn status(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
             _ => None,
         })
         .collect();
-    let mut panes = tmux.list_panes()?;
-    panes.sort_by(|a, b| a.window_id.cmp(&b.window_id));
-    println!("POSITION NAME RUNNING? LOG_LINES");
-    for tmux::PaneInfo {
-        window_id,
-        window_name,
-        tty,
-        pane_id,
-    } in panes
+    let old_layout = is_old_layout(dir);
+    let state = State::read(dir)?;
+    // In the bar, which the order of the windows stops following once
+    // a reload adds a slot, since its window is appended. Sessions of the
+    // old layout are restarted rather than reloaded, so theirs is in order.
+    let position = |pane: &tmux::PaneInfo| {
+        if old_layout {
+            Some(pane.window_index)
+        } else if pane.window_name == NAME_PISTA {
+            Some(0)
+        } else {
+            state
+                .slots
+                .iter()
+                .position(|s| s.name == pane.window_name)
+                .map(|i| i + 1)
+        }
+    };
+    let mut panes: Vec<(Option<usize>, tmux::PaneInfo)> = tmux
+        .list_panes()?
+        .into_iter()
+        .map(|p| (position(&p), p))
+        .collect();
+    // Unknown ones last.
+    panes.sort_by_key(|(position, _)| (position.is_none(), *position));
+    println!("POSITION NAME RUNNING? LOG_LINES ROTATED");
+    for (
+        position,
+        tmux::PaneInfo {
+            window_index,
+            window_id,
+            window_name,
+            tty,
+            pane_id,
+        },
+    ) in panes
     {
         assert_eq!(window_id, pane_id);
-        let log_file = dir
-            .join(slot_dir_name(window_id, &window_name))
-            .join(NAME_ERR);
+        let log_file = if old_layout {
+            dir.join(format!("{}-{}", window_index, window_name))
+        } else {
+            dir.join(&window_name)
+        }
+        .join(NAME_ERR);
         // TODO Per log level? How to not assume log format?
         let log_lines = match crate::fs::read_to_string(&log_file) {
             Ok(log) => log.lines().count(),
@@ -57,11 +93,23 @@ pub fn status(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
             }
         };
         let is_running = fg.get(&tty).map_or("NO", |_| "YES");
+        let rotated = logs::rotated(&log_file).len();
+        let position = match position {
+            Some(position) => position.to_string(),
+            None => {
+                tracing::warn!(
+                    "Window {:?} is neither of pista, nor of a slot \
+                    in the session state.",
+                    window_name
+                );
+                "-".to_string()
+            }
+        };
         println!(
-            "{} {} {} {}",
-            &window_id, &window_name, is_running, log_lines
+            "{} {} {} {} {}",
+            &position, &window_name, is_running, log_lines, rotated
         );
-        if window_id == 0 && window_name != NAME_PISTA {
+        if old_layout && window_index == 0 && window_name != NAME_PISTA {
             tracing::warn!(
                 "Expected zeroth window name to be {:?}, but it was: {:?}",
                 NAME_PISTA,
@@ -69,6 +117,190 @@ pub fn status(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
             );
         }
     }
+    for state::Skipped { name, reason } in state.skipped {
+        println!("- {} SKIPPED 0 0", name);
+        tracing::info!("Skipped slot {:?}: {}", name, reason);
+    }
+    Ok(())
+}
+
+pub fn check(cfg: &Cfg) -> Result<()> {
+    for (i, slot) in zip(1.., cfg.pista.slots.iter()) {
+        let (slot_name, slot_dir) = cfg.slot_name_and_dir(i, slot);
+        let context = || format!("Invalid slot: {:?}", &slot_name);
+        slot.env_vars().with_context(context)?;
+        slot.sandbox_paths(&slot_dir).with_context(context)?;
+        if let Some(cwd) = slot.cwd().with_context(context)? {
+            if !cwd.is_dir() {
+                tracing::warn!(
+                    "Slot {:?} cwd is not a directory: {:?}",
+                    &slot_name,
+                    &cwd
+                );
+            }
+        }
+        match slot.skip_reason().with_context(context)? {
+            None => println!("{} {}", i, &slot_name),
+            Some(reason) => {
+                println!("{} {} (skip: {})", i, &slot_name, reason)
+            }
+        }
+        for line in slot.cmd.lines() {
+            println!("    {}", line);
+        }
+    }
+    for (slot_name, var) in &cfg.unknown_vars {
+        tracing::warn!(
+            "Slot {:?} has {{{}}}, which is not a known variable, so it is \
+            left as it is. If it is meant to be one, check its name, or \
+            define it in vars. Otherwise, double its braces.",
+            slot_name,
+            var
+        );
+    }
+    println!("OK");
+    Ok(())
+}
+
+/// Whether a session was started, as opposed to only configured.
+pub fn is_started(cfg: &Cfg) -> bool {
+    State::path(&cfg.slots_fifos_dir).exists()
+}
+
+/// Whether the session was started by a version which named the dirs
+/// of pista and the slots by their position too, like 0-pista.
+fn is_old_layout(dir: &Path) -> bool {
+    !State::path(dir).exists()
+        && dir.join(format!("0-{}", NAME_PISTA)).exists()
+}
+
+/// Removes all in the dir, but the notifications history.
+fn remove_session_dir(dir: &Path) -> Result<()> {
+    remove_dir_contents(dir, &notify::history::path(dir))
+}
+
+fn remove_dir_contents(dir: &Path, keep: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path == keep {
+            continue;
+        }
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the notifications emitted, the oldest first.
+pub fn notifications(
+    cfg: &Cfg,
+    since: Option<OffsetDateTime>,
+    slot: Option<&str>,
+) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    for notify::history::Entry { notification, sent } in
+        notify::history::read(&cfg.slots_fifos_dir)?
+    {
+        let n = notification;
+        if slot.is_some() && n.slot.as_deref() != slot {
+            continue;
+        }
+        let time = OffsetDateTime::parse(&n.time, &Rfc3339)
+            .with_context(|| format!("Invalid time: {:?}", &n.time))?;
+        if since.is_some_and(|since| time < since) {
+            continue;
+        }
+        match write_notification(&mut stdout, &n, time, sent) {
+            Ok(()) => {}
+            // Like when piped to head.
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+fn write_notification(
+    out: &mut impl Write,
+    n: &notify::Notification,
+    time: OffsetDateTime,
+    sent: bool,
+) -> std::io::Result<()> {
+    let code = n.code.map_or("-".to_string(), |code| code.to_string());
+    writeln!(
+        out,
+        "{} {} {} {}{}",
+        timestamp::format(time),
+        n.event.as_str(),
+        n.slot.as_deref().unwrap_or("-"),
+        code,
+        if sent { "" } else { " (dropped)" }
+    )?;
+    writeln!(out, "    {}", &n.summary)?;
+    for line in n.body.lines() {
+        writeln!(out, "    {}", line)?;
+    }
+    Ok(())
+}
+
+/// Prints the err log of the slot, or pista, from its oldest rotated file,
+/// or pista's out log. Filtering by time needs the lines timestamped,
+/// which pista's are not.
+pub fn logs(
+    cfg: &Cfg,
+    name: &str,
+    out: bool,
+    current: bool,
+    since: Option<OffsetDateTime>,
+    until: Option<OffsetDateTime>,
+) -> Result<()> {
+    if out && name != NAME_PISTA {
+        bail!("Only pista has an out log. Out of slots is their FIFO.");
+    }
+    if since.is_some() || until.is_some() {
+        if !cfg.logs.timestamps {
+            bail!(
+                "Filtering by time needs the lines timestamped. \
+                Set: logs.timestamps = true"
+            );
+        }
+        if name == NAME_PISTA {
+            bail!(
+                "Filtering by time is only for slots. \
+                Pista's logs are not timestamped."
+            );
+        }
+    }
+    let path = cfg.slots_fifos_dir.join(name).join(if out {
+        NAME_OUT
+    } else {
+        NAME_ERR
+    });
+    if !path.exists() {
+        bail!("Log not found: {:?}", &path);
+    }
+    let mut readers = logs::open(&path)?;
+    if current {
+        readers.drain(..readers.len() - 1);
+    }
+    let mut stdout = std::io::stdout().lock();
+    let mut time = None;
+    for mut reader in readers {
+        let result = if since.is_none() && until.is_none() {
+            std::io::copy(&mut reader, &mut stdout).map(|_| ())
+        } else {
+            logs::filter(reader, &mut stdout, since, until, &mut time)
+        };
+        match result {
+            Ok(()) => {}
+            // Like when piped to head.
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
     Ok(())
 }
 
@@ -76,67 +308,271 @@ pub fn attach(tmux: &Tmux) -> Result<()> {
     tmux.attach()
 }
 
-pub fn restart(cfg: &Cfg, tmux: &mut Tmux) -> Result<()> {
+pub fn restart(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
     let _ = stop(cfg, tmux);
     start(cfg, tmux)
 }
 
-pub fn start(cfg: &Cfg, tmux: &mut Tmux) -> Result<()> {
+pub fn start(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
+    let started = std::time::Instant::now();
     let base_dir = &cfg.slots_fifos_dir;
     crate::fs::create_dir_all(base_dir)?;
+    notify::write(cfg)?;
     tmux.new_session(base_dir)?;
-    let pista_slot_specs = start_slots(cfg, tmux)?;
-    start_pista(cfg, tmux, pista_slot_specs)
+    let (slots, skipped, _) = start_slots(cfg, tmux, &State::default())?;
+    let pista = pista_args(cfg, &slots);
+    start_pista(cfg, tmux, &pista)?;
+    let state = State {
+        profile: cfg.profile.clone(),
+        pista,
+        slots,
+        skipped,
+    };
+    state.write(base_dir)?;
+    let mut body = format!(
+        "startup time: {:.1} s\nslots: {} started, {} skipped",
+        started.elapsed().as_secs_f32(),
+        state.slots.len(),
+        state.skipped.len()
+    );
+    let len_unknown: Vec<&str> = state
+        .slots
+        .iter()
+        .filter(|s| s.len_unknown)
+        .map(|s| s.name.as_str())
+        .collect();
+    if !len_unknown.is_empty() {
+        body.push_str(&format!(
+            "\nlength detection failed: {}",
+            len_unknown.join(", ")
+        ));
+    }
+    notify::session_event(cfg, notify::Event::SessionStarted, body);
+    Ok(())
+}
+
+/// Applies the differences between the config and the running session,
+/// leaving alone the slots which did not change.
+pub fn reload(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
+    let base_dir = &cfg.slots_fifos_dir;
+    if is_old_layout(base_dir) {
+        tracing::warn!(
+            "Session started by an older version, with another layout of \
+            its dir, which slots cannot be reused from. Restarting it."
+        );
+        return restart(cfg, tmux);
+    }
+    let prev = State::read(base_dir)?;
+    notify::write(cfg)?;
+    let (slots, skipped, pista_stopped) = start_slots(cfg, tmux, &prev)?;
+    for prev_slot in &prev.slots {
+        if !slots.iter().any(|s| s.name == prev_slot.name) {
+            tracing::info!("Slot removed: {:?}", &prev_slot.name);
+            stop_slot(cfg, tmux, &prev_slot.name, false)?;
+        }
+    }
+    let pista = pista_args(cfg, &slots);
+    if pista != prev.pista {
+        tracing::info!("pista changed. Restarting.");
+        restart_pista(cfg, tmux, &pista)?;
+        notify::session_event(
+            cfg,
+            notify::Event::PistaRestarted,
+            "pista restarted, since its arguments changed.".to_string(),
+        );
+    } else if pista_stopped {
+        tracing::info!("pista unchanged. Starting it again.");
+        restart_pista(cfg, tmux, &pista)?;
+    } else {
+        tracing::info!("pista unchanged.");
+    }
+    State {
+        profile: cfg.profile.clone(),
+        pista,
+        slots,
+        skipped,
+    }
+    .write(base_dir)
 }
 
-fn start_pista(
+pub fn profiles(cfg: &Cfg) -> Result<()> {
+    let active = State::read(&cfg.slots_fifos_dir)?.profile;
+    for name in &cfg.profiles {
+        let mark = if active.as_ref() == Some(name) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{} {}", mark, name);
+    }
+    Ok(())
+}
+
+/// Searched is whether the files were looked for in the default locations,
+/// rather than given on command line.
+pub fn config_paths(files: &[PathBuf], searched: bool) -> Result<()> {
+    if searched {
+        println!("searched:");
+        for path in cfg::paths::candidates()? {
+            let found = if files.contains(&path) {
+                "FOUND  "
+            } else if path.exists() {
+                "IGNORED"
+            } else {
+                "MISSING"
+            };
+            println!("    {} {}", found, path.display());
+        }
+    }
+    println!("files:");
+    for path in cfg::paths::layers(files)? {
+        println!("    {}", path.display());
+    }
+    println!("keys:");
+    let origins = cfg::paths::origins(files)?;
+    let width = origins.keys().map(String::len).max().unwrap_or(0);
+    for (key, path) in origins {
+        println!("    {:width$} {}", key, path.display(), width = width);
+    }
+    Ok(())
+}
+
+/// Prints the changes, which upgrade the files to the current version of
+/// the config format, and applies them, if asked to.
+pub fn config_migrate(files: &[PathBuf], apply: bool) -> Result<()> {
+    let mut outdated = 0;
+    for path in cfg::paths::layers(files)? {
+        let mut doc = cfg::edit::Doc::open(&path)?;
+        let old = doc.doc.to_string();
+        let changed = cfg::migrate::document(&mut doc.doc)
+            .with_context(|| format!("Failed to migrate: {:?}", &path))?;
+        if !changed {
+            tracing::info!("Up to date: {:?}", &path);
+            continue;
+        }
+        outdated += 1;
+        let new = doc.doc.to_string();
+        let name = path.to_string_lossy();
+        print!(
+            "{}",
+            similar::TextDiff::from_lines(&old, &new)
+                .unified_diff()
+                .header(&name, &name)
+        );
+        if apply {
+            doc.commit(files)?;
+        }
+    }
+    if outdated > 0 && !apply {
+        tracing::info!("To apply the changes, run again with --apply");
+    }
+    Ok(())
+}
+
+fn pista_args(cfg: &Cfg, slots: &[state::Slot]) -> String {
+    let specs: Vec<&str> =
+        slots.iter().map(|s| s.pista_spec.as_str()).collect();
+    format!("{} {}", cfg.pista.to_arg_str(), specs.join(" "))
+}
+
+fn write_pista_run(
     cfg: &Cfg,
-    tmux: &Tmux,
-    pista_slot_specs: Vec<String>,
+    pista_dir: &Path,
+    pista_args: &str,
 ) -> Result<()> {
-    let pista_dir = cfg.slots_fifos_dir.join(slot_dir_name(0, NAME_PISTA));
-    crate::fs::create_dir_all(&pista_dir)?;
-    {
-        let mut run = crate::fs::file_create(pista_dir.join(NAME_RUN))?;
-        writeln!(run, "#! /bin/bash")?;
+    let mut run = crate::fs::file_create(pista_dir.join(NAME_RUN))?;
+    writeln!(run, "#! /bin/bash")?;
+    if cfg.logs.rotated() {
+        let exe = std::env::current_exe()?;
+        // Timestamps are for the slots, whose logs lack them.
+        let logs = cfg::Logs {
+            timestamps: false,
+            ..cfg.logs.clone()
+        };
+        let log = |name: &str| {
+            scripts::write_log(&exe, &pista_dir.join(name), &logs, None)
+        };
+        let out = scripts::helper(NAME_OUT, &log(NAME_OUT), &[]);
+        let err = scripts::helper(NAME_ERR, &log(NAME_ERR), &[NAME_OUT]);
+        writeln!(run, "{}\n{}", out, err)?;
         writeln!(
             run,
-            "pista {} {} >> ./{} 2>> ./{};",
-            &cfg.pista.to_arg_str(),
-            pista_slot_specs.join(" "),
-            NAME_OUT,
-            NAME_ERR
+            "pista {} {} {};",
+            pista_args,
+            scripts::to_helper(1, NAME_OUT),
+            scripts::to_helper(2, NAME_ERR)
         )?;
         writeln!(run, "code=$?")?;
+        writeln!(run, "{}", scripts::wait_helpers(&[NAME_OUT, NAME_ERR]))?;
+    } else {
         writeln!(
             run,
-            "log=$({})",
-            &scripts::tail_log(
-                NAME_ERR,
-                cfg.notifications.log_lines_limit,
-                &cfg.notifications.indent,
-                cfg.notifications.width_limit
-            )
+            "pista {} >> ./{} 2>> ./{};",
+            pista_args, NAME_OUT, NAME_ERR
         )?;
-        writeln!(run, "body=\"code: $code\nlog:\n$log\"")?;
+        writeln!(run, "code=$?")?;
+    }
+    if cfg.notifications.enabled(notify::Event::PistaExited, None) {
+        writeln!(run, "body=\"code: $code\nlog:\"")?;
         writeln!(
             run,
             "{}",
-            scripts::notify_send_critical("'pista exited!'", "\"$body\"",)
+            scripts::notify(
+                &std::env::current_exe()?,
+                &notify::path(&cfg.slots_fifos_dir),
+                notify::Event::PistaExited,
+                None,
+                "code",
+                "body",
+                Some(&pista_dir.join(NAME_ERR))
+            )
         )?;
-        crate::fs::set_permissions(&run, PERM_OWNER_RWX)?;
-        run.sync_all()?;
     }
+    crate::fs::set_permissions(&run, PERM_OWNER_RWX)?;
+    run.sync_all()?;
+    Ok(())
+}
+
+fn start_pista(cfg: &Cfg, tmux: &Tmux, pista_args: &str) -> Result<()> {
+    let pista_dir = cfg.slots_fifos_dir.join(NAME_PISTA);
+    crate::fs::create_dir_all(&pista_dir)?;
+    write_pista_run(cfg, &pista_dir, pista_args)?;
     let term = tmux.zeroth_terminal(&pista_dir, NAME_PISTA)?;
     tmux.send_text(&term, &format!("./{}", NAME_RUN))?;
     tmux.send_enter(&term)
 }
 
+fn stop_pista(tmux: &Tmux) -> Result<()> {
+    let term = tmux
+        .find_terminal(NAME_PISTA)?
+        .ok_or_else(|| anyhow!("pista window not found"))?;
+    tmux.send_interrupt(&term)
+}
+
+fn restart_pista(cfg: &Cfg, tmux: &Tmux, pista_args: &str) -> Result<()> {
+    let pista_dir = cfg.slots_fifos_dir.join(NAME_PISTA);
+    write_pista_run(cfg, &pista_dir, pista_args)?;
+    let term = tmux
+        .find_terminal(NAME_PISTA)?
+        .ok_or_else(|| anyhow!("pista window not found"))?;
+    tmux.send_interrupt(&term)?;
+    tmux.send_text(&term, &format!("./{}", NAME_RUN))?;
+    tmux.send_enter(&term)
+}
+
 pub fn stop(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
+    // Before the session dir, where notifications are rate-limited, is gone.
+    if is_started(cfg) {
+        notify::session_event(
+            cfg,
+            notify::Event::SessionStopped,
+            format!("session: {}", &cfg.session),
+        );
+    }
     if let Err(err) = tmux.kill_session() {
         tracing::error!("Failure in kill session: {:?}", err);
     }
-    if let Err(err) = std::fs::remove_dir_all(&cfg.slots_fifos_dir) {
+    if let Err(err) = remove_session_dir(&cfg.slots_fifos_dir) {
         tracing::error!(
             "Failure in removal of slot directory: {:?}. Error: {:?}",
             &cfg.slots_fifos_dir,
@@ -159,20 +595,92 @@ pub fn stop(cfg: &Cfg, tmux: &Tmux) -> Result<()> {
     Ok(())
 }
 
+/// Kills the slot's window and removes its dir, except, if keep_fifo,
+/// the FIFO, for the slot to be started again in its place.
+fn stop_slot(
+    cfg: &Cfg,
+    tmux: &Tmux,
+    slot_name: &str,
+    keep_fifo: bool,
+) -> Result<()> {
+    match tmux.find_terminal(slot_name)? {
+        Some(term) => tmux.kill_window(&term)?,
+        None => tracing::warn!("Slot window not found: {:?}", slot_name),
+    }
+    let slot_dir = cfg.slots_fifos_dir.join(slot_name);
+    let result = if keep_fifo {
+        remove_dir_contents(&slot_dir, &slot_dir.join(NAME_OUT))
+    } else {
+        std::fs::remove_dir_all(&slot_dir).map_err(anyhow::Error::from)
+    };
+    if let Err(err) = result {
+        tracing::error!(
+            "Failure in removal of slot directory: {:?}. Error: {:?}",
+            &slot_dir,
+            err
+        );
+    }
+    Ok(())
+}
+
+/// Of what the slot's scripts are made of, so that changes to the config,
+/// which do not affect the slot, do not restart it. The rest of the
+/// notifications config is read when a notification is sent.
+fn slot_fingerprint(cfg: &Cfg, slot: &cfg::Slot) -> Result<String> {
+    use notify::Event;
+
+    let enabled = [Event::SlotRecovered, Event::SlotExited, Event::Alert]
+        .map(|e| cfg.notifications.enabled(e, Some(slot)));
+    let rules = slot_rules(cfg, slot);
+    let logs = writes_log(cfg, &rules).then_some(&cfg.logs);
+    let env_file = slot.env_file_data()?.map(|(_, data)| data);
+    state::fingerprint(&(slot, enabled, &rules, logs, env_file))
+}
+
+/// Of the slot's own alerts, and of all slots, unless disabled.
+fn slot_rules(cfg: &Cfg, slot: &cfg::Slot) -> alert::Rules {
+    let notif = &cfg.notifications;
+    if !notif.enabled(notify::Event::Alert, Some(slot)) {
+        return alert::Rules::default();
+    }
+    alert::Rules {
+        alerts: notif.alerts.iter().chain(&slot.alerts).cloned().collect(),
+        value_alerts: slot.value_alerts.clone(),
+    }
+}
+
+/// Whether the slot's err is written by the write-log helper, rather than
+/// appended to by the shell.
+fn writes_log(cfg: &Cfg, rules: &alert::Rules) -> bool {
+    !rules.alerts.is_empty() || cfg.logs.rotated() || cfg.logs.timestamps
+}
+
+/// Of the cmd file.
+fn slot_cmd(slot: &cfg::Slot) -> String {
+    format!("#! {}\n{}\n", slot.interpreter.display(), slot.cmd)
+}
+
+/// Length, if not given, is that of the previous start, if the cmd is
+/// the same, and otherwise it is read from the first line of the FIFO.
 fn start_slot(
-    notif: &cfg::Notifications,
+    cfg: &Cfg,
     slot: &cfg::Slot,
     slot_dir: &Path,
     slot_name: &str,
-    tmux: &mut Tmux,
-) -> Result<String> {
+    tmux: &Tmux,
+    prev_len: Option<usize>,
+) -> Result<state::Slot> {
+    let notif = &cfg.notifications;
     crate::fs::create_dir_all(slot_dir)?;
     let slot_pipe = slot_dir.join(NAME_OUT);
-    crate::fs::mkfifo(&slot_pipe)?;
+    // Kept when restarting the slot, so that pista needs no restart.
+    if !slot_pipe.exists() {
+        crate::fs::mkfifo(&slot_pipe)?;
+    }
+    let slot_cmd = slot_cmd(slot);
     {
         let mut cmd = crate::fs::file_create(slot_dir.join(NAME_CMD))?;
-        writeln!(cmd, "#! {}", slot.interpreter.display())?;
-        writeln!(cmd, "{}", slot.cmd)?;
+        write!(cmd, "{}", &slot_cmd)?;
         crate::fs::set_permissions(&cmd, PERM_OWNER_RWX)?;
         cmd.sync_all()?;
     }
@@ -183,29 +691,121 @@ fn start_slot(
         writeln!(run, "# which was written to ./{},", NAME_CMD)?;
         writeln!(run, "# adding output redirection and")?;
         writeln!(run, "# a notification in case of an unexpected exit.")?;
-        writeln!(
-            run,
-            "cd {:?} && ./{} > ./{} 2>> ./{};",
-            slot_dir, NAME_CMD, NAME_OUT, NAME_ERR
-        )?;
-        writeln!(run, "code=$?")?;
         writeln!(run, "slot_name={}", slot_name)?;
-        writeln!(
-            run,
-            "log=$({})",
-            &scripts::tail_log(
+        let exited = slot_dir.join(NAME_EXITED);
+        let recovered =
+            notif.enabled(notify::Event::SlotRecovered, Some(slot));
+        if recovered {
+            writeln!(run, "if [ -e {:?} ]; then", &exited)?;
+            writeln!(run, "    code=$(cat {:?})", &exited)?;
+            writeln!(run, "    rm -f {:?}", &exited)?;
+            writeln!(
+                run,
+                "    body=\"slot: $slot_name\nprevious code: $code\""
+            )?;
+            // In the background, not to delay the slot.
+            writeln!(
+                run,
+                "    {} &",
+                scripts::notify(
+                    &std::env::current_exe()?,
+                    &notify::path(&cfg.slots_fifos_dir),
+                    notify::Event::SlotRecovered,
+                    Some(slot_name),
+                    "code",
+                    "body",
+                    None
+                )
+            )?;
+            writeln!(run, "fi")?;
+        }
+        let rules = slot_rules(cfg, slot);
+        if !(rules.alerts.is_empty() && rules.value_alerts.is_empty()) {
+            alert::write(slot_dir, &rules)?;
+        }
+        let exe = std::env::current_exe()?;
+        let settings = notify::path(&cfg.slots_fifos_dir);
+        let rules_path = alert::path(slot_dir);
+        let mut helpers = vec![];
+        let out = if rules.value_alerts.is_empty() {
+            format!("> ./{}", NAME_OUT)
+        } else {
+            helpers.push((
+                NAME_OUT,
+                scripts::watch_out(
+                    &exe,
+                    &settings,
+                    slot_name,
+                    &rules_path,
+                    &slot_pipe,
+                ),
+            ));
+            scripts::to_helper(1, NAME_OUT)
+        };
+        let err = if !writes_log(cfg, &rules) {
+            format!("2>> ./{}", NAME_ERR)
+        } else {
+            let alerts = (!rules.alerts.is_empty()).then_some((
+                settings.as_path(),
+                slot_name,
+                rules_path.as_path(),
+            ));
+            helpers.push((
                 NAME_ERR,
-                notif.log_lines_limit,
-                &notif.indent,
-                notif.width_limit
-            )
-        )?;
-        writeln!(run, "body=\"slot: $slot_name\ncode: $code\nlog:\n$log\"")?;
-        writeln!(
-            run,
-            "{}",
-            scripts::notify_send_critical("'pista feed exited!'", "\"$body\"",)
-        )?;
+                scripts::write_log(
+                    &exe,
+                    &slot_dir.join(NAME_ERR),
+                    &cfg.logs,
+                    alerts,
+                ),
+            ));
+            scripts::to_helper(2, NAME_ERR)
+        };
+        // Started before the cmd, with their PIDs kept, to wait for just
+        // them, not for any other background job, like a notification.
+        let names: Vec<&str> = helpers.iter().map(|(name, _)| *name).collect();
+        for (i, (name, helper)) in helpers.iter().enumerate() {
+            writeln!(run, "{}", scripts::helper(name, helper, &names[..i]))?;
+        }
+        writeln!(run, "cd {:?} && (", slot_dir)?;
+        for line in launch_lines(slot, slot_dir)? {
+            writeln!(run, "    {}", line)?;
+        }
+        // Opening the FIFO also for reading (<>) keeps the feed alive
+        // through restarts of pista, instead of being killed by SIGPIPE.
+        writeln!(run, ") 3<> ./{} {} {};", NAME_OUT, out, err)?;
+        writeln!(run, "code=$?")?;
+        // Through the helper, if any, for the report to be timestamped
+        // and counted towards the rotation, like the rest of the log.
+        let report = if names.contains(&NAME_ERR) {
+            scripts::to_helper(1, NAME_ERR)
+        } else {
+            format!(">> ./{}", NAME_ERR)
+        };
+        writeln!(run, "{}", scripts::report_signal("code", &report))?;
+        if !names.is_empty() {
+            // For the helpers to write the last lines.
+            writeln!(run, "{}", scripts::wait_helpers(&names))?;
+        }
+        if recovered {
+            writeln!(run, "echo \"$code\" > {:?}", &exited)?;
+        }
+        if notif.enabled(notify::Event::SlotExited, Some(slot)) {
+            writeln!(run, "body=\"slot: $slot_name\ncode: $code\nlog:\"")?;
+            writeln!(
+                run,
+                "{}",
+                scripts::notify(
+                    &std::env::current_exe()?,
+                    &notify::path(&cfg.slots_fifos_dir),
+                    notify::Event::SlotExited,
+                    Some(slot_name),
+                    "code",
+                    "body",
+                    Some(&slot_dir.join(NAME_ERR))
+                )
+            )?;
+        }
         crate::fs::set_permissions(&run, PERM_OWNER_RWX)?;
         run.sync_all()?;
     }
@@ -213,8 +813,9 @@ fn start_slot(
     let dot_slash_run = format!("./{}", NAME_RUN);
     tmux.send_text(&term, &dot_slash_run)?;
     tmux.send_enter(&term)?;
-    let slot_len = match slot.len {
-        Some(len) => {
+    let mut len_unknown = false;
+    let slot_len = match (slot.len, prev_len) {
+        (Some(len), _) => {
             tracing::info!(
                 "User-defined slot length found: {}, for command: {:?}",
                 len,
@@ -222,7 +823,16 @@ fn start_slot(
             );
             len
         }
-        None => {
+        (None, Some(len)) => {
+            tracing::info!(
+                "Slot length kept from the previous start: {}, \
+                for unchanged command: {:?}",
+                len,
+                &slot.cmd
+            );
+            len
+        }
+        (None, None) => {
             tracing::warn!(
                 "User-defined slot length NOT found. \
                 Waiting for first line in FIFO: {:?}. \
@@ -242,11 +852,13 @@ fn start_slot(
                         &slot_pipe,
                         &default_len
                     );
+                    len_unknown = true;
                     default_len
                 }
                 Some(head) => {
                     // pista expects length in bytes. String::len already counts bytes,
                     // but I just want to be super-explicit:
+                    #[allow(clippy::needless_as_bytes)]
                     let len = head.as_bytes().len();
                     tracing::info!(
                         "Read slot length: {}. Restarting command: {:?}",
@@ -264,24 +876,128 @@ fn start_slot(
         }
     };
     let pista_slot_spec = format!("{:?} {} {}", slot_pipe, slot_len, slot.ttl);
-    Ok(pista_slot_spec)
+    Ok(state::Slot {
+        name: slot_name.to_string(),
+        fingerprint: slot_fingerprint(cfg, slot)?,
+        pista_spec: pista_slot_spec,
+        len_unknown,
+        cmd_fingerprint: state::fingerprint(&slot_cmd)?,
+    })
 }
 
-fn start_slots(cfg: &Cfg, tmux: &mut Tmux) -> Result<Vec<String>> {
-    let mut pista_slot_specs = Vec::new();
-    for (i, s) in zip(1.., cfg.pista.slots.iter()) {
-        let slot_name = match s.name {
-            None => i.to_string(),
-            Some(ref name) => name.to_string(),
-        };
-        let slot_dir = cfg.slots_fifos_dir.join(slot_dir_name(i, &slot_name));
-        let slot_spec =
-            start_slot(&cfg.notifications, s, &slot_dir, &slot_name, tmux)?;
-        pista_slot_specs.push(slot_spec);
+/// Lines of the subshell which replaces itself with the slot's cmd.
+fn launch_lines(slot: &cfg::Slot, slot_dir: &Path) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let cfg::Limits {
+        address_space,
+        open_files,
+        cpu_seconds,
+    } = slot.limits;
+    if let Some(bytes) = address_space {
+        // ulimit -v counts KiB.
+        let kib = bytes.div_ceil(1024);
+        lines.push(scripts::ulimit("-v", kib, "address space limit"));
+    }
+    if let Some(n) = open_files {
+        lines.push(scripts::ulimit("-n", n, "open files limit"));
+    }
+    if let Some(secs) = cpu_seconds {
+        // Soft only, so that the violation is reported as SIGXCPU,
+        // rather than the SIGKILL which follows reaching the hard limit.
+        lines.push(scripts::ulimit("-S -t", secs, "CPU time limit"));
     }
-    Ok(pista_slot_specs)
+    if let Some(n) = slot.nice {
+        lines.push(scripts::try_or_report(
+            &format!("renice -n {} -p $BASHPID > /dev/null", n),
+            "nice",
+        ));
+    }
+    if let Some(cfg::IoNice { class, level }) = slot.ionice {
+        let level = level.map_or(String::new(), |l| format!(" -n {}", l));
+        lines.push(scripts::try_or_report(
+            &format!("ionice -c {}{} -p $BASHPID", class as u8, level),
+            "ionice",
+        ));
+    }
+    if let Some(adj) = slot.oom_score_adj {
+        lines.push(scripts::try_or_report(
+            &format!("echo {} > /proc/$BASHPID/oom_score_adj", adj),
+            "oom_score_adj",
+        ));
+    }
+    let mut exec = vec!["exec".to_string()];
+    let vars = slot.env_vars()?;
+    if slot.clear_env || !vars.is_empty() {
+        exec.push(scripts::env(slot.clear_env, &vars));
+    }
+    if let Some((read, write)) = slot.sandbox_paths(slot_dir)? {
+        exec.push(format!("{:?} sandbox", std::env::current_exe()?));
+        exec.extend(read.iter().map(|p| format!("--read {:?}", p)));
+        exec.extend(write.iter().map(|p| format!("--write {:?}", p)));
+        exec.push("--".to_string());
+    }
+    match slot.cwd()? {
+        None => exec.push(format!("./{}", NAME_CMD)),
+        Some(cwd) => {
+            lines.push(format!("cd {:?} || exit 1", cwd));
+            exec.push(format!("{:?}", slot_dir.join(NAME_CMD)));
+        }
+    }
+    lines.push(exec.join(" "));
+    Ok(lines)
 }
 
-fn slot_dir_name(position: usize, name: &str) -> String {
-    format!("{}-{}", position, name)
+/// Starts the slots which are not already running, per the previous state,
+/// and those whose conditions hold. Returns also whether pista was stopped,
+/// for a restarted slot's length to be read from its FIFO, which pista
+/// would otherwise read first, so that pista needs to be started again.
+fn start_slots(
+    cfg: &Cfg,
+    tmux: &Tmux,
+    prev: &State,
+) -> Result<(Vec<state::Slot>, Vec<state::Skipped>, bool)> {
+    let mut slots = Vec::new();
+    let mut skipped = Vec::new();
+    let mut pista_stopped = false;
+    for (i, s) in zip(1.., cfg.pista.slots.iter()) {
+        let (slot_name, slot_dir) = cfg.slot_name_and_dir(i, s);
+        if let Some(reason) = s.skip_reason()? {
+            tracing::info!("Skipping slot {:?}: {}", &slot_name, &reason);
+            skipped.push(state::Skipped {
+                name: slot_name,
+                reason,
+            });
+            continue;
+        }
+        let prev_len = match prev.slot(&slot_name) {
+            Some(prev_slot)
+                if prev_slot.fingerprint == slot_fingerprint(cfg, s)? =>
+            {
+                tracing::info!("Slot unchanged: {:?}", &slot_name);
+                slots.push(prev_slot.clone());
+                continue;
+            }
+            Some(prev_slot) => {
+                tracing::info!("Slot changed: {:?}", &slot_name);
+                // pista, if not restarted, keeps reading the same FIFO.
+                stop_slot(cfg, tmux, &slot_name, true)?;
+                let cmd_fingerprint = state::fingerprint(&slot_cmd(s))?;
+                let prev_len = prev_slot
+                    .known_len()
+                    .filter(|_| prev_slot.cmd_fingerprint == cmd_fingerprint);
+                if s.len.is_none() && prev_len.is_none() && !pista_stopped {
+                    tracing::info!(
+                        "Stopping pista, to read the length of slot {:?}.",
+                        &slot_name
+                    );
+                    stop_pista(tmux)?;
+                    pista_stopped = true;
+                }
+                prev_len
+            }
+            None => None,
+        };
+        slots.push(start_slot(cfg, s, &slot_dir, &slot_name, tmux, prev_len)?);
+    }
+    Ok((slots, skipped, pista_stopped))
 }
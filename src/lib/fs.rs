@@ -1,7 +1,7 @@
 use std::{
     fmt::Debug,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Write},
     os::unix,
     path::Path,
     sync::mpsc,
@@ -84,6 +84,27 @@ pub fn create_dir_all(path: &Path) -> Result<()> {
         .with_context(|| format!("Failed to create dirs for path: {:?}", path))
 }
 
+/// Passes each line of stdin on to dst, as soon as it is read, and then
+/// to f, without its newline.
+pub fn pass_lines<W: Write>(
+    dst: &mut W,
+    mut f: impl FnMut(&str),
+) -> Result<()> {
+    let mut stdin = std::io::stdin().lock();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if stdin.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+        // Passed on first, so the line is not delayed by what f does.
+        dst.write_all(&buf)?;
+        dst.flush()?;
+        let line = String::from_utf8_lossy(&buf);
+        f(line.trim_end_matches('\n'));
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Internal
 // ----------------------------------------------------------------------------
@@ -1,6 +1,19 @@
 use tracing_subscriber::{filter, EnvFilter, FmtSubscriber};
 
 pub fn init(debug: bool) -> anyhow::Result<()> {
+    tracing::subscriber::set_global_default(subscriber(debug)?)?;
+    Ok(())
+}
+
+/// Logs of f, which runs before the logger is initialized, like the
+/// loading of the config, which may itself enable debug.
+pub fn scoped<T>(debug: bool, f: impl FnOnce() -> T) -> anyhow::Result<T> {
+    Ok(tracing::subscriber::with_default(subscriber(debug)?, f))
+}
+
+fn subscriber(
+    debug: bool,
+) -> anyhow::Result<impl tracing::Subscriber + Send + Sync> {
     let level = if debug {
         filter::LevelFilter::DEBUG.into()
     } else {
@@ -24,11 +37,9 @@ pub fn init(debug: bool) -> anyhow::Result<()> {
             // because rfc_3339() ends up printing microseconds and causes
             // variable width lines which do not align.
             tracing_subscriber::fmt::time::LocalTime::new(
-            time::macros::format_description!(
-                "[year]-[month]-[day] [hour]:[minute]:[second][offset_hour]:[offset_minute]"
+                crate::timestamp::FORMAT,
             ),
-        ))
+        )
         .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
-    Ok(())
+    Ok(subscriber)
 }
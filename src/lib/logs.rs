@@ -0,0 +1,281 @@
+//! Log files of the session: the slots' err, and pista's out and err.
+//!
+//! With rotation, they are written by a helper, rather than appended to by
+//! the shell, so that their size and age are checked as they grow. A file
+//! is rotated in place: its content is compressed into the newest of the
+//! rotated files, <name>.1.gz, the older ones shifted to <name>.2.gz and
+//! so on, and it is truncated, which the shell's appends survive.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use time::OffsetDateTime;
+
+use crate::cfg::Logs;
+
+/// Rotated files of the log, from the newest.
+pub fn rotated(path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|i| generation(path, i))
+        .take_while(|p| p.exists())
+        .collect()
+}
+
+fn generation(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}.gz", i));
+    PathBuf::from(name)
+}
+
+/// Readers of the rotated files of the log, from the oldest,
+/// followed by the log itself, if it exists.
+pub fn open(path: &Path) -> Result<Vec<Box<dyn BufRead>>> {
+    let mut readers: Vec<Box<dyn BufRead>> = Vec::new();
+    for p in rotated(path).iter().rev() {
+        let file = File::open(p)
+            .with_context(|| format!("Failed to open: {:?}", p))?;
+        readers.push(Box::new(BufReader::new(GzDecoder::new(file))));
+    }
+    if path.exists() {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open: {:?}", path))?;
+        readers.push(Box::new(BufReader::new(file)));
+    }
+    Ok(readers)
+}
+
+/// Last lines of the log, read through its newest rotated file too, for
+/// them not to be missing if it was rotated just before.
+pub fn tail(path: &Path, n: usize) -> Result<Vec<String>> {
+    let mut readers = open(path)?;
+    let newest = readers.len().saturating_sub(2);
+    let mut lines = VecDeque::new();
+    for reader in readers.drain(newest..) {
+        for line in reader.split(b'\n') {
+            lines.push_back(String::from_utf8_lossy(&line?).into_owned());
+            if lines.len() > n {
+                lines.pop_front();
+            }
+        }
+    }
+    Ok(lines.into())
+}
+
+/// Copies the lines within the time range, of which those without a time
+/// are taken to be from that of the nearest line before them which has it.
+/// Those before any with a time, like from before timestamps were turned
+/// on, are copied, since there is no telling when they were written.
+pub fn filter<R: BufRead, W: Write>(
+    src: R,
+    dst: &mut W,
+    since: Option<OffsetDateTime>,
+    until: Option<OffsetDateTime>,
+    time: &mut Option<OffsetDateTime>,
+) -> io::Result<()> {
+    for line in src.split(b'\n') {
+        let line = line?;
+        if let Some(t) =
+            crate::timestamp::parse_prefix(&String::from_utf8_lossy(&line))
+        {
+            *time = Some(t);
+        }
+        if time.is_some_and(|t| {
+            since.is_some_and(|since| t < since)
+                || until.is_some_and(|until| t > until)
+        }) {
+            continue;
+        }
+        dst.write_all(&line)?;
+        dst.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Appends lines of stdin to the log, timestamping and rotating it as
+/// configured, and passes each to f, once it is written.
+pub fn write(path: &Path, logs: &Logs, f: impl FnMut(&str)) -> Result<()> {
+    let mut writer = Writer::open(path, logs)?;
+    crate::fs::pass_lines(&mut writer, f)
+}
+
+/// Checks whether to rotate on flush, which is after each line.
+struct Writer<'a> {
+    path: &'a Path,
+    file: File,
+    logs: &'a Logs,
+
+    /// When the current content of the log started.
+    started: SystemTime,
+
+    /// Whether the next write starts a line, to be timestamped.
+    at_line_start: bool,
+}
+
+impl<'a> Writer<'a> {
+    fn open(path: &'a Path, logs: &'a Logs) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open: {:?}", path))?;
+        // Since it was last rotated, by a previous run of the helper.
+        let started = match std::fs::metadata(generation(path, 1)) {
+            Ok(meta) => meta.modified()?,
+            Err(_) => file.metadata()?.created().or_else(|_| {
+                file.metadata().and_then(|meta| meta.modified())
+            })?,
+        };
+        Ok(Self {
+            path,
+            file,
+            logs,
+            started,
+            at_line_start: true,
+        })
+    }
+
+    fn is_due(&self) -> Result<bool> {
+        let size = self.file.metadata()?.len();
+        if size == 0 {
+            return Ok(false);
+        }
+        let age = self.started.elapsed().unwrap_or_default().as_secs();
+        Ok(self.logs.max_size.is_some_and(|max| size >= max)
+            || self.logs.max_age.is_some_and(|max| age >= max))
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        tracing::debug!("Rotating log: {:?}", self.path);
+        // From the oldest, so that each is moved to a free name.
+        let rotated = rotated(self.path);
+        for (i, p) in (1..rotated.len() + 1).zip(rotated).rev() {
+            if i >= self.logs.keep {
+                std::fs::remove_file(&p)
+                    .with_context(|| format!("Failed to remove: {:?}", &p))?;
+            } else {
+                let next = generation(self.path, i + 1);
+                std::fs::rename(&p, &next).with_context(|| {
+                    format!("Failed to rename {:?} to {:?}", &p, &next)
+                })?;
+            }
+        }
+        if self.logs.keep > 0 {
+            let dst = generation(self.path, 1);
+            let mut src = File::open(self.path)?;
+            let mut gz = GzEncoder::new(
+                crate::fs::file_create(&dst)?,
+                Compression::default(),
+            );
+            io::copy(&mut src, &mut gz)?;
+            gz.finish()?.sync_all()?;
+        }
+        self.file.set_len(0)?;
+        self.started = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl Write for Writer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.logs.timestamps && self.at_line_start {
+            let time = crate::timestamp::format(crate::timestamp::now());
+            self.file.write_all(format!("{} ", time).as_bytes())?;
+        }
+        // Whole, so that a prefix is not repeated for the rest of it.
+        self.file.write_all(buf)?;
+        self.at_line_start = buf.ends_with(b"\n");
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let result =
+            self.is_due().and_then(
+                |due| {
+                    if due {
+                        self.rotate()
+                    } else {
+                        Ok(())
+                    }
+                },
+            );
+        // Better an oversized log than a lost line.
+        if let Err(err) = result {
+            tracing::error!(
+                "Failed to rotate log: {:?}. Error: {:?}",
+                self.path,
+                err
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    #[test]
+    fn filter() {
+        let src = "\
+            a\n\
+            2026-01-01 10:00:00+00:00 b\n\
+            c\n\
+            2026-01-01 11:00:00+00:00 d\n\
+            2026-01-01 12:00:00+00:00 e\n";
+        let time = |s| crate::timestamp::parse(s).unwrap();
+        let filter = |since, until| {
+            let mut dst = Vec::new();
+            let mut t = None;
+            super::filter(src.as_bytes(), &mut dst, since, until, &mut t)
+                .unwrap();
+            String::from_utf8(dst).unwrap()
+        };
+        assert_eq!(
+            "a\n2026-01-01 11:00:00+00:00 d\n2026-01-01 12:00:00+00:00 e\n",
+            filter(Some(time("2026-01-01T10:30:00Z")), None)
+        );
+        assert_eq!(
+            "a\n2026-01-01 10:00:00+00:00 b\nc\n",
+            filter(None, Some(time("2026-01-01T10:30:00Z")))
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let dir = std::env::temp_dir()
+            .join(format!("pistactl-test-logs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("err");
+        let logs = crate::cfg::Logs {
+            max_size: Some(4),
+            max_age: None,
+            keep: 2,
+            timestamps: false,
+        };
+        let mut writer = super::Writer::open(&path, &logs).unwrap();
+        for line in ["a\n", "bbbb\n", "c\n", "d\n", "eeee\n", "f\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+            writer.flush().unwrap();
+        }
+        assert_eq!(2, super::rotated(&path).len());
+        let mut content = String::new();
+        for mut reader in super::open(&path).unwrap() {
+            reader.read_to_string(&mut content).unwrap();
+        }
+        assert_eq!("c\nd\neeee\nf\n", content);
+        assert_eq!(vec!["eeee", "f"], super::tail(&path, 3).unwrap());
+        assert_eq!(vec!["f"], super::tail(&path, 1).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,13 @@
+pub mod alert;
 pub mod cfg;
 pub mod cmd;
 pub mod fs;
 pub mod logger;
+pub mod logs;
+pub mod notify;
+pub mod sandbox;
+pub mod state;
+pub mod timestamp;
 pub mod tmux;
 
 mod process;
@@ -0,0 +1,846 @@
+//! Notifications of events in the running session, like a slot exiting.
+//!
+//! Scripts of the session report events with the internal notify command,
+//! which reads the notifications config from a snapshot taken when the
+//! session was started or reloaded, so that it neither depends on the
+//! config files being valid at the time, nor misses the overrides.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+pub mod history;
+mod limit;
+
+use crate::{
+    cfg::{Backend, Cfg, Notifications},
+    tmux::Tmux,
+};
+
+const FILE_NAME: &str = "notifications.toml";
+
+const FILE_NAME_ID: &str = "notification_id";
+
+/// PID of the process waiting for an action on the notification.
+const FILE_NAME_WAITER: &str = "notification_waiter";
+
+const DBUS_NAME: &str = "org.freedesktop.Notifications";
+const DBUS_PATH: &str = "/org/freedesktop/Notifications";
+
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    PistaExited,
+    SlotExited,
+    SlotRecovered,
+    PistaRestarted,
+    SessionStarted,
+    SessionStopped,
+
+    /// Of an alert rule matching a slot's stderr or output.
+    Alert,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Urgency {
+    Low = 0,
+    #[default]
+    Normal = 1,
+    Critical = 2,
+}
+
+impl Urgency {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PistaExited => "pista-exited",
+            Self::SlotExited => "slot-exited",
+            Self::SlotRecovered => "slot-recovered",
+            Self::PistaRestarted => "pista-restarted",
+            Self::SessionStarted => "session-started",
+            Self::SessionStopped => "session-stopped",
+            Self::Alert => "alert",
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Notification {
+    pub event: Event,
+    pub urgency: Urgency,
+
+    /// Local, in RFC 3339 format.
+    pub time: String,
+    pub summary: String,
+    pub body: String,
+    pub slot: Option<String>,
+
+    /// Exit code.
+    pub code: Option<i32>,
+}
+
+impl Notification {
+    pub fn new(
+        event: Event,
+        urgency: Urgency,
+        summary: String,
+        slot: Option<String>,
+        code: Option<i32>,
+        body: String,
+    ) -> Result<Self> {
+        Ok(Self {
+            event,
+            urgency,
+            time: crate::timestamp::now()
+                .format(&time::format_description::well_known::Rfc3339)?,
+            summary,
+            body,
+            slot,
+            code,
+        })
+    }
+}
+
+impl Notification {
+    /// Name of the tmux window of the slot, or pista, which caused it.
+    fn window(&self) -> &str {
+        self.slot.as_deref().unwrap_or(crate::cmd::NAME_PISTA)
+    }
+
+    /// Keys and labels, alternating, as D-Bus expects them.
+    fn actions(&self) -> Vec<&'static str> {
+        let restart = match self.event {
+            Event::PistaExited => "Restart pista",
+            Event::SlotExited => "Restart slot",
+            _ => return vec![],
+        };
+        vec![
+            ACTION_RESTART,
+            restart,
+            ACTION_LOGS,
+            "Open logs",
+            ACTION_ATTACH,
+            "Attach",
+        ]
+    }
+}
+
+pub trait Notifier {
+    fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// What the notify command needs to know about the session.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Settings {
+    pub sock: String,
+    pub session: String,
+    pub notifications: Notifications,
+}
+
+impl From<&Cfg> for Settings {
+    fn from(cfg: &Cfg) -> Self {
+        Self {
+            sock: cfg.sock.clone(),
+            session: cfg.session.clone(),
+            notifications: cfg.notifications.clone(),
+        }
+    }
+}
+
+/// Notifier configured by the settings, of the session in dir.
+pub fn notifier(settings: &Settings, dir: &Path) -> Result<Box<dyn Notifier>> {
+    let cfg = &settings.notifications;
+    let notifier: Box<dyn Notifier> = match cfg.backend {
+        Backend::NotifySend => Box::new(NotifySend),
+        Backend::Dbus => Box::new(Dbus {
+            dir: dir.to_path_buf(),
+            actions: cfg.actions.then(|| Actions {
+                tmux: Tmux::new(&settings.sock, &settings.session),
+                terminal: cfg.terminal.clone(),
+            }),
+        }),
+        Backend::Command => Box::new(UserCommand {
+            cmd: cfg.command.clone().ok_or_else(|| {
+                anyhow!("Notifications backend command requires: command")
+            })?,
+        }),
+        Backend::File => Box::new(File {
+            path: cfg.file.clone().ok_or_else(|| {
+                anyhow!("Notifications backend file requires: file")
+            })?,
+        }),
+        Backend::None => Box::new(Null),
+    };
+    Ok(notifier)
+}
+
+/// Path of the settings snapshot in the session dir.
+pub fn path(dir: &Path) -> PathBuf {
+    dir.join(FILE_NAME)
+}
+
+pub fn write(cfg: &Cfg) -> Result<()> {
+    let path = path(&cfg.slots_fifos_dir);
+    let settings = Settings::from(cfg);
+    let data = toml::to_string(&settings)?;
+    std::fs::write(&path, data).with_context(|| {
+        format!("Failed to write notifications config to: {:?}", &path)
+    })
+}
+
+pub fn read(path: &Path) -> Result<Settings> {
+    let data = crate::fs::read_to_string(path)?;
+    let settings = toml::from_str(&data).with_context(|| {
+        format!("Invalid notifications config in: {:?}", path)
+    })?;
+    Ok(settings)
+}
+
+/// Sends a notification of the event, as configured in the snapshot.
+/// Whether the event is enabled was decided when the script calling this
+/// was written, since it also depends on the slot's config.
+pub fn send(
+    settings_path: &Path,
+    event: Event,
+    slot: Option<String>,
+    code: Option<i32>,
+    body: String,
+    log: Option<&Path>,
+) -> Result<()> {
+    let settings = read(settings_path)?;
+    let dir = settings_path.parent().unwrap_or(Path::new("."));
+    let body = match log {
+        None => body,
+        Some(log) => {
+            let tail =
+                tail_log(log, &settings.notifications).unwrap_or_else(|err| {
+                    tracing::error!(
+                        "Failed to read log: {:?}. Error: {:?}",
+                        log,
+                        err
+                    );
+                    String::new()
+                });
+            format!("{}\n{}", body, tail)
+        }
+    };
+    emit(&settings, dir, event, slot, code, body)
+}
+
+/// Last lines of the log, without terminal color codes, indented and
+/// chopped to the width limit.
+fn tail_log(log: &Path, cfg: &Notifications) -> Result<String> {
+    let ansi = regex::Regex::new(r"\x1b\[[0-9;]*m")?;
+    let lines: Vec<String> = crate::logs::tail(log, cfg.log_lines_limit)?
+        .iter()
+        .map(|line| {
+            format!("{}{}", cfg.indent, ansi.replace_all(line, ""))
+                .chars()
+                .take(cfg.width_limit)
+                .collect()
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// Sends a notification of an event of the session itself, if enabled.
+/// Failure is only reported, since the event already happened.
+pub fn session_event(cfg: &Cfg, event: Event, body: String) {
+    if !cfg.notifications.enabled(event, None) {
+        return;
+    }
+    let settings = Settings::from(cfg);
+    let dir = &cfg.slots_fifos_dir;
+    if let Err(err) = emit(&settings, dir, event, None, None, body) {
+        tracing::error!("Failed to notify of {:?}. Error: {:?}", event, err);
+    }
+}
+
+fn emit(
+    settings: &Settings,
+    dir: &Path,
+    event: Event,
+    slot: Option<String>,
+    code: Option<i32>,
+    body: String,
+) -> Result<()> {
+    let cfg = &settings.notifications;
+    let policy = cfg.policy(event);
+    let summary = policy.summary(slot.as_deref(), code, &settings.session)?;
+    let mut notification =
+        Notification::new(event, policy.urgency, summary, slot, code, body)?;
+    let sent = limit::admit(dir, cfg, &settings.session, &mut notification)?;
+    let entry = history::Entry { notification, sent };
+    if let Err(err) = history::append(dir, cfg.history_limit, &entry) {
+        tracing::error!("Failed to record notification. Error: {:?}", err);
+    }
+    let notification = entry.notification;
+    if !sent {
+        return Ok(());
+    }
+    tracing::debug!("Notifying with {:?}: {:?}", cfg.backend, &notification);
+    notifier(settings, dir)?.notify(&notification)
+}
+
+struct NotifySend;
+
+impl Notifier for NotifySend {
+    fn notify(&self, n: &Notification) -> Result<()> {
+        let status = Command::new("notify-send")
+            .args(["-u", n.urgency.as_str(), "-a", crate::NAME!()])
+            .arg(&n.summary)
+            .arg(&n.body)
+            .status()
+            .context("Failed to execute notify-send")?;
+        if !status.success() {
+            bail!("notify-send failed: {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// Talks to the notification server directly, replacing the previous
+/// notification of the same event from the same slot, or from pista,
+/// rather than stacking a new one on top of it. The IDs to replace are
+/// kept in the slot's dir, one per event, so that, say, an alert does not
+/// take the place of the notification of the slot's exit.
+///
+/// With actions, the wait for one to be invoked, or for the notification
+/// to be closed, is left to a detached process, so that the slot's run
+/// script, which sent it, is not held up, and can be restarted meanwhile.
+struct Dbus {
+    dir: PathBuf,
+    actions: Option<Actions>,
+}
+
+struct Actions {
+    tmux: Tmux,
+    terminal: String,
+}
+
+const ACTION_RESTART: &str = "restart";
+const ACTION_LOGS: &str = "logs";
+const ACTION_ATTACH: &str = "attach";
+
+impl Dbus {
+    /// Returns the ID of the notification.
+    fn notify_on(
+        &self,
+        conn: &zbus::blocking::Connection,
+        n: &Notification,
+        actions: &[&str],
+    ) -> Result<u32> {
+        let event = clap::ValueEnum::to_possible_value(&n.event)
+            .map_or(String::new(), |v| v.get_name().to_string());
+        let id_path = self
+            .dir
+            .join(n.window())
+            .join(format!("{}.{}", FILE_NAME_ID, event));
+        let prev_id: u32 = std::fs::read_to_string(&id_path)
+            .ok()
+            .and_then(|id| id.trim().parse().ok())
+            .unwrap_or(0);
+        let id = dbus_notify(conn, n, prev_id, actions)?;
+        if let Err(err) = std::fs::write(&id_path, id.to_string()) {
+            tracing::warn!(
+                "Failed to save notification ID to: {:?}. Error: {:?}",
+                &id_path,
+                err
+            );
+        }
+        Ok(id)
+    }
+
+    /// Starts the internal wait-action command, in a session of its own,
+    /// so that it outlives the caller and the slot's window. Returns once
+    /// it has subscribed to the signals, not to miss a quick click.
+    fn spawn_waiter(&self, window: &str) -> Result<std::process::Child> {
+        // Forked, for the child, which is waited for, to exit right away.
+        let mut child = Command::new("setsid")
+            .arg("--fork")
+            .arg(std::env::current_exe()?)
+            .args(["wait-action", "--settings"])
+            .arg(path(&self.dir))
+            .args(["--window", window])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to spawn the wait for notification actions")?;
+        let mut ready = String::new();
+        if let Some(stdout) = child.stdout.take() {
+            BufReader::new(stdout).read_line(&mut ready)?;
+        }
+        if ready.is_empty() {
+            let _ = child.wait();
+            bail!("Wait for notification actions failed to start");
+        }
+        Ok(child)
+    }
+
+    fn act(&self, actions: &Actions, window: &str, key: &str) -> Result<()> {
+        tracing::info!("Action {:?} invoked for: {:?}", key, window);
+        match key {
+            ACTION_RESTART => {
+                let term =
+                    actions.tmux.find_terminal(window)?.ok_or_else(|| {
+                        anyhow!("Window not found: {:?}", window)
+                    })?;
+                // Executed by the shell of the window, which is idle,
+                // since the run script exited.
+                actions.tmux.send_text(
+                    &term,
+                    &format!("./{}", crate::cmd::NAME_RUN),
+                )?;
+                actions.tmux.send_enter(&term)
+            }
+            ACTION_LOGS => {
+                let log = self.dir.join(window).join(crate::cmd::NAME_ERR);
+                let log = crate::scripts::quote(&log.to_string_lossy());
+                open_terminal(&actions.terminal, &format!("less +G {}", log))
+            }
+            ACTION_ATTACH => {
+                let cmd: Vec<String> = actions
+                    .tmux
+                    .attach_window_cmd(window)
+                    .iter()
+                    .map(|word| crate::scripts::quote(word))
+                    .collect();
+                open_terminal(&actions.terminal, &cmd.join(" "))
+            }
+            _ => bail!("Unknown action: {:?}", key),
+        }
+    }
+}
+
+impl Notifier for Dbus {
+    fn notify(&self, n: &Notification) -> Result<()> {
+        let conn = zbus::blocking::Connection::session()
+            .context("Failed to connect to D-Bus session bus")?;
+        let mut actions = match self.actions {
+            Some(_) if dbus_has_actions(&conn)? => n.actions(),
+            _ => vec![],
+        };
+        let waiter = if actions.is_empty() {
+            None
+        } else {
+            match self.spawn_waiter(n.window()) {
+                Ok(waiter) => Some(waiter),
+                Err(err) => {
+                    tracing::error!(
+                        "Notifying without actions. Error: {:?}",
+                        err
+                    );
+                    actions.clear();
+                    None
+                }
+            }
+        };
+        let result = self.notify_on(&conn, n, &actions);
+        if let Some(mut waiter) = waiter {
+            // Without an ID, if the notification failed, it just exits.
+            if let (Ok(id), Some(mut stdin)) = (&result, waiter.stdin.take()) {
+                writeln!(stdin, "{}", id)?;
+            }
+            waiter.wait()?;
+        }
+        result.map(|_| ())
+    }
+}
+
+/// Waits for an action to be invoked on the notification of the window,
+/// and executes it. Tells, by a line on stdout, that it is subscribed to
+/// the signals, and then reads the ID of the notification from stdin.
+pub fn wait_action(settings_path: &Path, window: &str) -> Result<()> {
+    let settings = read(settings_path)?;
+    let dir = settings_path.parent().unwrap_or(Path::new("."));
+    let conn = zbus::blocking::Connection::session()
+        .context("Failed to connect to D-Bus session bus")?;
+    let signals = dbus_signals(&conn)?;
+    let waiter = dir.join(window).join(FILE_NAME_WAITER);
+    let pid = std::process::id().to_string();
+    std::fs::write(&waiter, &pid)
+        .with_context(|| format!("Failed to write to: {:?}", &waiter))?;
+    println!("ready");
+    let mut id = String::new();
+    std::io::stdin().read_line(&mut id)?;
+    let Ok(id) = id.trim().parse() else {
+        return Ok(());
+    };
+    let Some(key) = dbus_wait_action(signals, id)? else {
+        return Ok(());
+    };
+    // A notification replaced by a newer one keeps its ID, so the waiter
+    // for the newer one, which took over, got the action too.
+    if std::fs::read_to_string(&waiter).ok() != Some(pid) {
+        tracing::debug!("Superseded wait for action on: {:?}", window);
+        return Ok(());
+    }
+    let cfg = &settings.notifications;
+    let dbus = Dbus {
+        dir: dir.to_path_buf(),
+        actions: None,
+    };
+    let actions = Actions {
+        tmux: Tmux::new(&settings.sock, &settings.session),
+        terminal: cfg.terminal.clone(),
+    };
+    dbus.act(&actions, window, &key)
+}
+
+/// Spawned in the background, not to keep the slot's window busy.
+fn open_terminal(terminal: &str, cmd: &str) -> Result<()> {
+    let cmd = format!("{} {}", terminal, cmd);
+    tracing::debug!("Opening terminal: {:?}", &cmd);
+    Command::new("sh")
+        .args(["-c", &cmd])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to open terminal: {:?}", &cmd))?;
+    Ok(())
+}
+
+fn dbus_call<B, R>(
+    conn: &zbus::blocking::Connection,
+    method: &str,
+    body: &B,
+) -> Result<R>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+    R: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+{
+    let reply = conn
+        .call_method(Some(DBUS_NAME), DBUS_PATH, Some(DBUS_NAME), method, body)
+        .with_context(|| format!("Failed to call D-Bus method: {}", method))?;
+    let r: R = reply.body().deserialize()?;
+    Ok(r)
+}
+
+fn dbus_has_actions(conn: &zbus::blocking::Connection) -> Result<bool> {
+    let capabilities: Vec<String> = dbus_call(conn, "GetCapabilities", &())?;
+    Ok(capabilities.iter().any(|c| c == "actions"))
+}
+
+/// Returns the ID of the notification, to replace it with the next one.
+fn dbus_notify(
+    conn: &zbus::blocking::Connection,
+    n: &Notification,
+    replaces_id: u32,
+    actions: &[&str],
+) -> Result<u32> {
+    use zbus::zvariant::Value;
+
+    let hints: HashMap<&str, Value> = HashMap::from([
+        ("urgency", Value::U8(n.urgency as u8)),
+        ("desktop-entry", Value::from(crate::NAME!())),
+    ]);
+    dbus_call(
+        conn,
+        "Notify",
+        &(
+            crate::NAME!(),
+            replaces_id,
+            "",
+            &n.summary,
+            &n.body,
+            actions,
+            hints,
+            -1i32,
+        ),
+    )
+}
+
+fn dbus_signals(
+    conn: &zbus::blocking::Connection,
+) -> Result<zbus::blocking::MessageIterator> {
+    let rule = zbus::MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .interface(DBUS_NAME)?
+        .path(DBUS_PATH)?
+        .build();
+    Ok(zbus::blocking::MessageIterator::for_match_rule(
+        rule, conn, None,
+    )?)
+}
+
+/// Key of the action invoked on the notification, or None if it was
+/// closed without one.
+fn dbus_wait_action(
+    signals: zbus::blocking::MessageIterator,
+    id: u32,
+) -> Result<Option<String>> {
+    for msg in signals {
+        let msg = msg?;
+        let header = msg.header();
+        match header.member().map(|m| m.as_str()) {
+            Some("ActionInvoked") => {
+                let (msg_id, key): (u32, String) = msg.body().deserialize()?;
+                if msg_id == id {
+                    return Ok(Some(key));
+                }
+            }
+            Some("NotificationClosed") => {
+                let (msg_id, _reason): (u32, u32) =
+                    msg.body().deserialize()?;
+                if msg_id == id {
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Executed by sh, with the notification in JSON on stdin.
+struct UserCommand {
+    cmd: String,
+}
+
+impl Notifier for UserCommand {
+    fn notify(&self, n: &Notification) -> Result<()> {
+        let mut child = Command::new("sh")
+            .args(["-c", &self.cmd])
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to execute notification command: {:?}",
+                    self.cmd
+                )
+            })?;
+        if let Some(mut stdin) = child.stdin.take() {
+            serde_json::to_writer(&mut stdin, n)?;
+            writeln!(stdin)?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("Notification command failed: {:?}: {}", self.cmd, status);
+        }
+        Ok(())
+    }
+}
+
+/// Appends the notification in JSON, one per line.
+struct File {
+    path: PathBuf,
+}
+
+impl Notifier for File {
+    fn notify(&self, n: &Notification) -> Result<()> {
+        let mut line = serde_json::to_string(n)?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| {
+                format!("Failed to append notification to: {:?}", self.path)
+            })
+    }
+}
+
+struct Null;
+
+impl Notifier for Null {
+    fn notify(&self, n: &Notification) -> Result<()> {
+        tracing::debug!("Notifications disabled. Dropping: {:?}", n);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{Event, Notification, Urgency};
+
+    /// App name, replaced ID and urgency.
+    type Call = (String, u32, u8);
+
+    struct Server {
+        calls: Arc<Mutex<Vec<Call>>>,
+        last_id: u32,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl Server {
+        fn get_capabilities(&self) -> Vec<String> {
+            vec!["actions".to_string()]
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &mut self,
+            app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            _summary: String,
+            _body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, zbus::zvariant::OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let urgency = hints
+                .get("urgency")
+                .and_then(|u| u8::try_from(u).ok())
+                .unwrap_or(u8::MAX);
+            self.calls
+                .lock()
+                .unwrap()
+                .push((app_name, replaces_id, urgency));
+            if replaces_id == 0 {
+                self.last_id += 1;
+                self.last_id
+            } else {
+                replaces_id
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn dbus() {
+        use zbus::blocking::connection::Builder;
+
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let calls: Arc<Mutex<Vec<Call>>> = Arc::default();
+        let server = Server {
+            calls: calls.clone(),
+            last_id: 0,
+        };
+        let server = Builder::address(address.trim())
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at("/org/freedesktop/Notifications", server)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client =
+            Builder::address(address.trim()).unwrap().build().unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("pistactl-test-dbus-{}", std::process::id()));
+        let dbus = super::Dbus {
+            dir: dir.clone(),
+            actions: None,
+        };
+        let notify = |event, urgency, slot: Option<&str>| {
+            let name = slot.unwrap_or(crate::cmd::NAME_PISTA);
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            let slot = slot.map(String::from);
+            let n = Notification::new(
+                event,
+                urgency,
+                "".into(),
+                slot,
+                Some(1),
+                "".into(),
+            )
+            .unwrap();
+            dbus.notify_on(&client, &n, &[])
+        };
+        let results = [
+            notify(Event::SlotExited, Urgency::Low, Some("a")),
+            notify(Event::SlotExited, Urgency::Low, Some("a")),
+            notify(Event::Alert, Urgency::Normal, Some("a")),
+            notify(Event::PistaExited, Urgency::Critical, None),
+        ];
+        let invoked = (|| {
+            assert!(super::dbus_has_actions(&client)?);
+            let signals = super::dbus_signals(&client)?;
+            let n = Notification::new(
+                Event::SlotExited,
+                Urgency::Normal,
+                "".into(),
+                Some("b".into()),
+                None,
+                "".into(),
+            )?;
+            let id = super::dbus_notify(&client, &n, 0, &n.actions())?;
+            server.emit_signal(
+                None::<&str>,
+                super::DBUS_PATH,
+                super::DBUS_NAME,
+                "NotificationClosed",
+                &(id + 1, 2u32),
+            )?;
+            server.emit_signal(
+                None::<&str>,
+                super::DBUS_PATH,
+                super::DBUS_NAME,
+                "ActionInvoked",
+                &(id, "attach"),
+            )?;
+            super::dbus_wait_action(signals, id)
+        })();
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let ids: Vec<u32> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(vec![1, 1, 2, 3], ids);
+        assert_eq!(Some("attach".to_string()), invoked.unwrap());
+        let app = || crate::NAME!().to_string();
+        assert_eq!(
+            vec![
+                (app(), 0, 0),
+                (app(), 1, 0),
+                (app(), 0, 1),
+                (app(), 0, 2),
+                (app(), 0, 1)
+            ],
+            *calls.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Every notification emitted, so that what it said, like the log of a
+//! crashed slot, is not lost once it disappears from the desktop.
+//! Kept in the session dir, one JSON entry per line, across restarts.
+
+use std::{
+    io::{BufRead, BufReader, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use super::Notification;
+
+pub const FILE_NAME: &str = "notifications.jsonl";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub notification: Notification,
+
+    /// Whether it was let through by the rate limits.
+    pub sent: bool,
+}
+
+pub fn path(dir: &Path) -> PathBuf {
+    dir.join(FILE_NAME)
+}
+
+/// Appends the entry, dropping the oldest ones beyond the limit.
+pub fn append(dir: &Path, limit: usize, entry: &Entry) -> Result<()> {
+    if limit == 0 {
+        return Ok(());
+    }
+    let path = path(dir);
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open: {:?}", &path))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock: {:?}", &path))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    file.rewind()?;
+    let lines = BufReader::new(&file)
+        .lines()
+        .collect::<std::io::Result<Vec<String>>>()?;
+    if lines.len() > limit {
+        let mut data = lines[lines.len() - limit..].join("\n");
+        data.push('\n');
+        file.set_len(0)?;
+        file.write_all(data.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Entries in the order they were emitted. Unreadable ones are skipped,
+/// so that a line cut by a crash does not hide the rest.
+pub fn read(dir: &Path) -> Result<Vec<Entry>> {
+    let path = path(dir);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let data = crate::fs::read_to_string(&path)?;
+    let entries = data
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                tracing::warn!(
+                    "Invalid entry in {:?}: {:?}. Error: {:?}",
+                    &path,
+                    line,
+                    err
+                );
+                None
+            }
+        })
+        .collect();
+    Ok(entries)
+}
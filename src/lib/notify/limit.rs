@@ -0,0 +1,213 @@
+//! Rate limits and coalescing, so that a flapping slot does not flood the
+//! desktop. Since each notification is sent by a separate process, recent
+//! ones are recorded in the session dir, in a file locked while deciding.
+//!
+//! There is no process to send a summary once a burst is over, so the
+//! dropped notifications are summarized in the next one let through.
+
+use std::{
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use crate::cfg::Notifications;
+
+use super::{Event, Notification};
+
+const FILE_NAME: &str = "notifications_recent.json";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Record {
+    /// Unix seconds.
+    time: i64,
+
+    /// Of the slot, or pista.
+    window: String,
+    event: Event,
+
+    /// Fingerprint of the body.
+    body: String,
+    sent: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Send,
+
+    /// With a summary of the ones dropped since the previous one sent.
+    Summarize {
+        dropped: usize,
+        since: i64,
+    },
+    Drop(&'static str),
+}
+
+/// Whether the notification should be sent. It may be changed into
+/// a summary of the ones dropped before it, by the burst summary of the
+/// event's policy.
+pub fn admit(
+    dir: &Path,
+    cfg: &Notifications,
+    session: &str,
+    n: &mut Notification,
+) -> Result<bool> {
+    let path = dir.join(FILE_NAME);
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open: {:?}", &path))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock: {:?}", &path))?;
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+    let mut records: Vec<Record> = if data.trim().is_empty() {
+        vec![]
+    } else {
+        serde_json::from_str(&data)
+            .with_context(|| format!("Invalid records in: {:?}", &path))?
+    };
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let record = Record {
+        time: now,
+        window: n.window().to_string(),
+        event: n.event,
+        body: crate::state::fingerprint(&n.body)?,
+        sent: true,
+    };
+    let decision = decide(&records, cfg, &record);
+    tracing::debug!("Rate limit decision: {:?}", &decision);
+    let sent = match decision {
+        Decision::Send => true,
+        Decision::Summarize { dropped, since } => {
+            n.summary = cfg.policy(n.event).burst_summary(
+                n.slot.as_deref(),
+                n.code,
+                session,
+                dropped + 1,
+                &duration(now - since),
+            )?;
+            true
+        }
+        Decision::Drop(reason) => {
+            tracing::info!("Notification dropped, due to {}: {:?}", reason, n);
+            false
+        }
+    };
+    let keep = cfg.rate_limit_window.max(cfg.coalesce_window) as i64;
+    records.retain(|r| now - r.time <= keep);
+    records.push(Record { sent, ..record });
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(serde_json::to_string(&records)?.as_bytes())?;
+    Ok(sent)
+}
+
+fn decide(records: &[Record], cfg: &Notifications, new: &Record) -> Decision {
+    let within = |r: &&Record, secs: u64| new.time - r.time < secs as i64;
+    let sent_in_window = || {
+        records
+            .iter()
+            .filter(|r| r.sent)
+            .filter(|r| within(r, cfg.rate_limit_window))
+    };
+    let own: Vec<&Record> = records
+        .iter()
+        .filter(|r| r.window == new.window && r.event == new.event)
+        .collect();
+    if let Some(last) = own.iter().rev().find(|r| r.sent) {
+        if last.body == new.body && within(last, cfg.coalesce_window) {
+            return Decision::Drop("coalescing");
+        }
+    }
+    if let Some(limit) = cfg.slot_rate_limit {
+        if sent_in_window().filter(|r| r.window == new.window).count() >= limit
+        {
+            return Decision::Drop("slot rate limit");
+        }
+    }
+    if let Some(limit) = cfg.global_rate_limit {
+        if sent_in_window().count() >= limit {
+            return Decision::Drop("global rate limit");
+        }
+    }
+    let dropped: Vec<&&Record> =
+        own.iter().rev().take_while(|r| !r.sent).collect();
+    match dropped.last() {
+        None => Decision::Send,
+        Some(first) => Decision::Summarize {
+            dropped: dropped.len(),
+            since: first.time,
+        },
+    }
+}
+
+fn duration(secs: i64) -> String {
+    if secs < 60 {
+        format!("{} s", secs)
+    } else {
+        format!("{} min", (secs + 59) / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, Event, Record};
+
+    #[test]
+    fn decide() {
+        let cfg = crate::cfg::Notifications {
+            rate_limit_window: 300,
+            slot_rate_limit: Some(2),
+            global_rate_limit: Some(3),
+            coalesce_window: 60,
+            ..Default::default()
+        };
+        let record = |time, window: &str, body: &str, sent| Record {
+            time,
+            window: window.to_string(),
+            event: Event::SlotExited,
+            body: body.to_string(),
+            sent,
+        };
+        let mut records = vec![];
+        let mut decide = |time, window, body| {
+            let new = record(time, window, body, true);
+            let decision = super::decide(&records, &cfg, &new);
+            let sent = !matches!(decision, Decision::Drop(_));
+            records.push(Record { sent, ..new });
+            decision
+        };
+        assert_eq!(Decision::Send, decide(0, "a", "1"));
+        assert_eq!(Decision::Drop("coalescing"), decide(10, "a", "1"));
+        assert_eq!(
+            Decision::Summarize {
+                dropped: 1,
+                since: 10
+            },
+            decide(20, "a", "2")
+        );
+        assert_eq!(Decision::Drop("slot rate limit"), decide(30, "a", "3"));
+        assert_eq!(Decision::Send, decide(40, "b", "1"));
+        assert_eq!(Decision::Drop("global rate limit"), decide(50, "c", "1"));
+        assert_eq!(Decision::Drop("slot rate limit"), decide(299, "a", "4"));
+        assert_eq!(
+            Decision::Summarize {
+                dropped: 2,
+                since: 30
+            },
+            decide(320, "a", "5")
+        );
+        assert_eq!(
+            Decision::Summarize {
+                dropped: 1,
+                since: 50
+            },
+            decide(360, "c", "1")
+        );
+    }
+}
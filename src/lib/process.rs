@@ -1,4 +1,8 @@
-use std::{io::Read, path::PathBuf, str::FromStr};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::{anyhow, bail, Error, Result};
 
@@ -38,6 +42,11 @@ pub fn list() -> Result<Vec<Info>> {
     Ok(list)
 }
 
+pub fn hostname() -> Result<String> {
+    let path = Path::new("/proc/sys/kernel/hostname");
+    Ok(crate::fs::read_to_string(path)?.trim().to_string())
+}
+
 pub fn exec(cmd: &str, args: &[&str]) -> Result<String> {
     let mut child = std::process::Command::new(cmd)
         .args(args)
@@ -72,6 +81,20 @@ pub fn exec(cmd: &str, args: &[&str]) -> Result<String> {
     })
 }
 
+/// Runs the command, silently, reporting only whether it succeeded.
+pub fn test(cmd: &str, args: &[&str]) -> Result<bool> {
+    let status = std::process::Command::new(cmd)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_err(|e| {
+            Error::from(e).context(format!("Failed to spawn {cmd:?} {args:?}"))
+        })?;
+    Ok(status.success())
+}
+
 pub fn run(cmd: &str, args: &[&str]) -> Result<()> {
     let _ = exec(cmd, args)?;
     Ok(())
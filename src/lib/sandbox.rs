@@ -0,0 +1,64 @@
+use std::{os::unix::process::CommandExt, path::PathBuf};
+
+use anyhow::{anyhow, Error, Result};
+use landlock::{
+    path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr,
+    RulesetCreatedAttr, RulesetStatus, ABI,
+};
+
+/// Paths which nearly every program needs, in order to even start.
+pub const SYSTEM_READ: [&str; 6] =
+    ["/bin", "/sbin", "/usr", "/lib", "/lib64", "/etc"];
+pub const SYSTEM_WRITE: [&str; 1] = ["/dev/null"];
+
+/// Restricts filesystem access of the current process to the given paths,
+/// using Landlock, then replaces it with cmd. Read paths are also allowed
+/// to be executed.
+///
+/// Does not fail when the kernel lacks Landlock support, but reports it
+/// and executes cmd unrestricted.
+pub fn exec(
+    read: &[PathBuf],
+    write: &[PathBuf],
+    cmd: &[String],
+) -> Result<()> {
+    let (prog, args) = cmd
+        .split_first()
+        .ok_or_else(|| anyhow!("Missing command to sandbox"))?;
+    for path in read.iter().chain(write.iter()) {
+        if !path.exists() {
+            tracing::warn!(
+                "Sandbox path does not exist, skipping: {:?}",
+                path
+            );
+        }
+    }
+    let abi = ABI::V2;
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(read, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(write, AccessFs::from_all(abi)))?
+        .restrict_self()?;
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => {
+            tracing::debug!("Landlock sandbox fully enforced.")
+        }
+        RulesetStatus::PartiallyEnforced => {
+            tracing::warn!(
+                "Landlock sandbox only partially enforced. \
+                The kernel supports an older Landlock ABI than {:?}.",
+                abi
+            )
+        }
+        RulesetStatus::NotEnforced => {
+            tracing::error!(
+                "Landlock is not supported by this kernel, \
+                or is disabled. Running {:?} WITHOUT a sandbox.",
+                prog
+            )
+        }
+    }
+    let err = std::process::Command::new(prog).args(args).exec();
+    Err(Error::from(err).context(format!("Failed to exec {:?}", cmd)))
+}
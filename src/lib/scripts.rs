@@ -1,88 +1,250 @@
-pub const SED_STRIP_ANSI_CODES: &str = r"sed 's/\x1b\[[0-9;]*m//g'";
+use std::path::Path;
 
-pub fn awk_indent_lines(indent: &str) -> String {
-    format!(r#"awk -v indent={:?} '{{print indent $0}}'"#, indent)
-}
+use clap::ValueEnum;
+
+use crate::{cfg::Logs, notify};
 
-pub fn awk_chop_lines(width: usize) -> String {
-    format!(r#"awk -v width={} '{{print substr($0, 1, width)}}'"#, width)
+/// Runs the internal notify command of the current executable,
+/// with the exit code and body taken from the given shell variables,
+/// and the last lines of the log, if any, appended to the body.
+pub fn notify(
+    exe: &Path,
+    settings: &Path,
+    event: notify::Event,
+    slot: Option<&str>,
+    code_var: &str,
+    body_var: &str,
+    log: Option<&Path>,
+) -> String {
+    let event = event
+        .to_possible_value()
+        .map_or(String::new(), |v| v.get_name().to_string());
+    let mut words = vec![
+        quote(&exe.to_string_lossy()),
+        "notify".to_string(),
+        "--settings".to_string(),
+        quote(&settings.to_string_lossy()),
+        "--event".to_string(),
+        event,
+        "--code".to_string(),
+        format!("\"${}\"", code_var),
+    ];
+    if let Some(slot) = slot {
+        words.push("--slot".to_string());
+        words.push(quote(slot));
+    }
+    if let Some(log) = log {
+        words.push("--log".to_string());
+        words.push(quote(&log.to_string_lossy()));
+    }
+    words.push("--".to_string());
+    words.push(format!("\"${}\"", body_var));
+    words.join(" ")
 }
 
-pub fn notify_send_critical(subject: &str, body: &str) -> String {
-    format!("notify-send -u critical {} {}", subject, body)
+/// Runs the internal watch-out command of the current executable,
+/// which passes stdin on to the FIFO, alerting as the rules say.
+pub fn watch_out(
+    exe: &Path,
+    settings: &Path,
+    slot: &str,
+    rules: &Path,
+    fifo: &Path,
+) -> String {
+    [
+        quote(&exe.to_string_lossy()),
+        "watch-out".to_string(),
+        "--settings".to_string(),
+        quote(&settings.to_string_lossy()),
+        "--slot".to_string(),
+        quote(slot),
+        "--rules".to_string(),
+        quote(&rules.to_string_lossy()),
+        "--fifo".to_string(),
+        quote(&fifo.to_string_lossy()),
+    ]
+    .join(" ")
 }
 
-pub fn tail_log(
-    file: &str,
-    lines: usize,
-    indent: &str,
-    width_limit: usize,
+/// Runs the internal write-log command of the current executable, which
+/// appends stdin to the file, rotating it as configured, and, if given
+/// the settings, slot and rules, alerting as the rules say.
+pub fn write_log(
+    exe: &Path,
+    file: &Path,
+    logs: &Logs,
+    alerts: Option<(&Path, &str, &Path)>,
 ) -> String {
+    let mut words = vec![
+        quote(&exe.to_string_lossy()),
+        "write-log".to_string(),
+        "--file".to_string(),
+        quote(&file.to_string_lossy()),
+    ];
+    if logs.rotated() {
+        if let Some(max_size) = logs.max_size {
+            words.push(format!("--max-size {}", max_size));
+        }
+        if let Some(max_age) = logs.max_age {
+            words.push(format!("--max-age {}", max_age));
+        }
+        words.push(format!("--keep {}", logs.keep));
+    }
+    if logs.timestamps {
+        words.push("--timestamps".to_string());
+    }
+    if let Some((settings, slot, rules)) = alerts {
+        words.push("--settings".to_string());
+        words.push(quote(&settings.to_string_lossy()));
+        words.push("--slot".to_string());
+        words.push(quote(slot));
+        words.push("--rules".to_string());
+        words.push(quote(&rules.to_string_lossy()));
+    }
+    words.join(" ")
+}
+
+/// Starts the helper cmd, reading what is written to the file descriptor
+/// in the shell variable {name}_fd, and keeps its PID in {name}_pid.
+/// The helpers started before are closed in it, for each of them to get
+/// EOF once the shell and the command close theirs, not only once all the
+/// later ones, and anything they spawned, exited.
+pub fn helper(name: &str, cmd: &str, before: &[&str]) -> String {
+    let mut words = vec![cmd.to_string()];
+    words.extend(before.iter().map(|name| format!("{{{}_fd}}>&-", name)));
     format!(
-        "tail -n {} {} | {} | {} | {}",
-        lines,
-        file,
-        SED_STRIP_ANSI_CODES,
-        awk_indent_lines(indent),
-        awk_chop_lines(width_limit)
+        "exec {{{}_fd}}> >(exec {})\n{}_pid=$!",
+        name,
+        words.join(" "),
+        name
+    )
+}
+
+/// Redirection of a file descriptor to the named helper.
+pub fn to_helper(fd: u8, name: &str) -> String {
+    format!("{}>&${}_fd", fd, name)
+}
+
+/// Closes the file descriptors of the named helpers, and waits for them
+/// to write the last of what they got.
+pub fn wait_helpers(names: &[&str]) -> String {
+    let fds: Vec<String> = names
+        .iter()
+        .map(|name| format!("{{{}_fd}}>&-", name))
+        .collect();
+    let pids: Vec<String> =
+        names.iter().map(|name| format!("${}_pid", name)).collect();
+    format!(
+        "exec {}\nwait {} 2> /dev/null",
+        fds.join(" "),
+        pids.join(" ")
+    )
+}
+
+/// Single-quotes a string for the shell, so nothing in it gets expanded.
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+pub fn env(clear: bool, vars: &[(String, String)]) -> String {
+    let mut words = vec!["env".to_string()];
+    if clear {
+        words.push("-i".to_string());
+    }
+    for (key, val) in vars {
+        words.push(quote(&format!("{}={}", key, val)));
+    }
+    words.join(" ")
+}
+
+/// Runs cmd, reporting its failure on stderr, but not aborting.
+pub fn try_or_report(cmd: &str, what: &str) -> String {
+    format!(
+        "{} || echo {} >&2",
+        cmd,
+        quote(&format!("{}: failed to set {}", crate::NAME!(), what))
+    )
+}
+
+pub fn ulimit(flags: &str, value: u64, what: &str) -> String {
+    try_or_report(&format!("ulimit {} {}", flags, value), what)
+}
+
+/// Reports termination by a signal, which is how most limit violations
+/// manifest, i.e. SIGXCPU for CPU time and SIGKILL from the OOM killer.
+/// Redirect is of the report, which is written to stdout.
+pub fn report_signal(code_var: &str, redirect: &str) -> String {
+    format!(
+        "[ \"${code}\" -gt 128 ] && \
+        echo \"{name}: cmd terminated by signal SIG$(kill -l $((${code} - 128)))\" \
+        {redirect}",
+        code = code_var,
+        name = crate::NAME!(),
+        redirect = redirect,
     )
 }
 
 #[cfg(test)]
 mod tests {
     #[test]
-    fn awk_indent_lines() {
+    fn helpers() {
         assert_eq!(
-            "awk -v indent=\"_\" '{print indent $0}'",
-            super::awk_indent_lines("_")
-        )
-    }
-
-    #[test]
-    fn awk_chop_lines() {
+            "exec {out_fd}> >(exec cat)\nout_pid=$!",
+            super::helper("out", "cat", &[])
+        );
         assert_eq!(
-            "awk -v width=5 '{print substr($0, 1, width)}'",
-            super::awk_chop_lines(5)
-        )
+            "exec {err_fd}> >(exec cat {out_fd}>&-)\nerr_pid=$!",
+            super::helper("err", "cat", &["out"])
+        );
+        assert_eq!("2>&$err_fd", super::to_helper(2, "err"));
+        assert_eq!(
+            "exec {out_fd}>&- {err_fd}>&-\nwait $out_pid $err_pid 2> /dev/null",
+            super::wait_helpers(&["out", "err"])
+        );
     }
 
     #[test]
-    fn tail_log() {
-        assert_eq!(
-            r#"tail -n 5 ./file | sed 's/\x1b\[[0-9;]*m//g' | awk -v indent="_" '{print indent $0}' | awk -v width=5 '{print substr($0, 1, width)}'"#,
-            super::tail_log("./file", 5, "_", 5)
-        )
+    fn quote() {
+        assert_eq!("'a b'", super::quote("a b"));
+        assert_eq!(r"'$x'", super::quote("$x"));
+        assert_eq!(r"'it'\''s'", super::quote("it's"));
     }
 
     #[test]
-    fn notify_simple() {
+    fn env() {
+        assert_eq!("env", super::env(false, &[]));
         assert_eq!(
-            "notify-send -u critical subject body",
-            super::notify_send_critical("subject", "body")
-        )
+            "env -i 'A=1' 'B=x y'",
+            super::env(
+                true,
+                &[
+                    ("A".to_string(), "1".to_string()),
+                    ("B".to_string(), "x y".to_string())
+                ]
+            )
+        );
     }
 
     #[test]
-    fn notify_compound_no_quotes() {
+    fn ulimit() {
         assert_eq!(
-            r#"notify-send -u critical subject $(tail -n 5 ./file | sed 's/\x1b\[[0-9;]*m//g' | awk -v indent="_" '{print indent $0}' | awk -v width=5 '{print substr($0, 1, width)}')"#,
-            super::notify_send_critical(
-                "subject",
-                &format!("$({})", super::tail_log("./file", 5, "_", 5))
-            )
-        )
+            "ulimit -n 8 || echo 'pistactl: failed to set open files limit' >&2",
+            super::ulimit("-n", 8, "open files limit")
+        );
     }
 
     #[test]
-    fn notify_compound_with_quotes() {
+    fn notify() {
         assert_eq!(
-            r#"notify-send -u critical 'foo bar baz' $(tail -n 5 "./file" | sed 's/\x1b\[[0-9;]*m//g' | awk -v indent="_" '{print indent $0}' | awk -v width=5 '{print substr($0, 1, width)}')"#,
-            super::notify_send_critical(
-                "'foo bar baz'",
-                &format!(
-                    "$({})",
-                    super::tail_log(&format!("{:?}", "./file"), 5, "_", 5)
-                )
+            r#"'/bin/pistactl' notify --settings '/tmp/x y' --event slot-exited --code "$code" --slot 'a' --log '/tmp/x y/a/err' -- "$body""#,
+            super::notify(
+                std::path::Path::new("/bin/pistactl"),
+                std::path::Path::new("/tmp/x y"),
+                crate::notify::Event::SlotExited,
+                Some("a"),
+                "code",
+                "body",
+                Some(std::path::Path::new("/tmp/x y/a/err"))
             )
         )
     }
@@ -0,0 +1,132 @@
+//! What was started in the running session, so that it can later be
+//! changed without restarting everything.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+const FILE_NAME: &str = "state.toml";
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    pub profile: Option<String>,
+
+    /// Arguments pista was started with.
+    pub pista: String,
+
+    /// In the order they appear in the bar.
+    pub slots: Vec<Slot>,
+
+    /// Slots not started because their conditions did not hold.
+    #[serde(default)]
+    pub skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Skipped {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Slot {
+    pub name: String,
+
+    /// Of the slot's config. Change means the slot has to be restarted.
+    pub fingerprint: String,
+
+    /// FIFO, length and TTL, as passed to pista.
+    pub pista_spec: String,
+
+    /// Length was not given and could not be read from the first line,
+    /// so pista got 0.
+    #[serde(default)]
+    pub len_unknown: bool,
+
+    /// Of the cmd file, from whose first line of output the length was
+    /// read, if it was not given.
+    #[serde(default)]
+    pub cmd_fingerprint: String,
+}
+
+impl State {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(FILE_NAME)
+    }
+
+    /// Missing state is not an error, since the session may have been
+    /// started by an older version, but it is the same as an empty one.
+    pub fn read(dir: &Path) -> Result<Self> {
+        let path = Self::path(dir);
+        if !path.exists() {
+            tracing::warn!("Session state not found: {:?}", &path);
+            return Ok(Self::default());
+        }
+        let data = crate::fs::read_to_string(&path)?;
+        let state = toml::from_str(&data).with_context(|| {
+            format!("Invalid session state in: {:?}", &path)
+        })?;
+        Ok(state)
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let path = Self::path(dir);
+        let data = toml::to_string(self)?;
+        std::fs::write(&path, data).with_context(|| {
+            format!("Failed to write session state to: {:?}", &path)
+        })
+    }
+
+    pub fn slot(&self, name: &str) -> Option<&Slot> {
+        self.slots.iter().find(|s| s.name == name)
+    }
+}
+
+impl Slot {
+    /// As passed to pista, unless it is unknown.
+    pub fn known_len(&self) -> Option<usize> {
+        if self.len_unknown {
+            return None;
+        }
+        self.pista_spec.rsplit(' ').nth(1)?.parse().ok()
+    }
+}
+
+/// Hash of the value's JSON, by FNV-1a, which, unlike the std hashers,
+/// is the same across versions of Rust, and so of pistactl.
+pub fn fingerprint<T: serde::Serialize>(x: &T) -> Result<String> {
+    let data = serde_json::to_vec(x)?;
+    let hash = data.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    Ok(format!("{:016x}", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn known_len() {
+        let slot = |pista_spec: &str, len_unknown| super::Slot {
+            name: "a".to_string(),
+            fingerprint: String::new(),
+            pista_spec: pista_spec.to_string(),
+            len_unknown,
+            cmd_fingerprint: String::new(),
+        };
+        assert_eq!(
+            Some(19),
+            slot(r#""/tmp/a b/out" 19 1"#, false).known_len()
+        );
+        assert_eq!(None, slot(r#""/tmp/a b/out" 0 1"#, true).known_len());
+    }
+
+    #[test]
+    fn fingerprint() {
+        // Of the JSON "", pinned, for sessions to survive upgrades.
+        assert_eq!("07cc7607b4949e25", super::fingerprint(&"").unwrap());
+        assert_ne!(
+            super::fingerprint(&("a", 1)).unwrap(),
+            super::fingerprint(&("a", 2)).unwrap()
+        );
+    }
+}
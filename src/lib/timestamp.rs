@@ -0,0 +1,76 @@
+use anyhow::{anyhow, Result};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// As in the logs: local, to the second, and of constant width.
+/// RFC 3339, with a space in place of T, as it allows.
+pub const FORMAT: &[time::format_description::FormatItem<'static>] = time::macros::format_description!(
+    "[year]-[month]-[day] [hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+);
+
+/// Of a time in FORMAT.
+const FORMAT_LEN: usize = 25;
+
+pub fn now() -> OffsetDateTime {
+    OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc())
+}
+
+/// Either absolute, in RFC 3339 or the log format, or relative to now,
+/// as a number of s, m, h or d ago, like 30m.
+pub fn parse(s: &str) -> Result<OffsetDateTime> {
+    let s = s.trim();
+    if let Ok(t) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Ok(t);
+    }
+    if let Ok(t) = OffsetDateTime::parse(s, FORMAT) {
+        return Ok(t);
+    }
+    let invalid = || {
+        anyhow!(
+            "Invalid time: {:?}. Expected RFC 3339, or a duration ago, \
+            like 30m, 2h or 7d",
+            s
+        )
+    };
+    let unit_at = s.len().checked_sub(1).ok_or_else(invalid)?;
+    let (n, unit) = s.split_at(unit_at);
+    let n: i64 = n.parse().map_err(|_| invalid())?;
+    let ago = match unit {
+        "s" => time::Duration::seconds(n),
+        "m" => time::Duration::minutes(n),
+        "h" => time::Duration::hours(n),
+        "d" => time::Duration::days(n),
+        _ => return Err(invalid()),
+    };
+    Ok(now() - ago)
+}
+
+pub fn format(t: OffsetDateTime) -> String {
+    t.format(FORMAT).unwrap_or_else(|_| t.to_string())
+}
+
+/// Time at the start of the line, if it is in FORMAT.
+pub fn parse_prefix(line: &str) -> Option<OffsetDateTime> {
+    let prefix = line.get(..FORMAT_LEN)?;
+    OffsetDateTime::parse(prefix, FORMAT).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse() {
+        let t = super::parse("2024-01-02T03:04:05+01:00").unwrap();
+        assert_eq!(t, super::parse("2024-01-02 03:04:05+01:00").unwrap());
+        assert_eq!(1704161045, t.unix_timestamp());
+        let ago = super::now() - super::parse("2h").unwrap();
+        assert!((ago - time::Duration::hours(2)).abs().whole_seconds() < 2);
+        assert!(super::parse("2w").is_err());
+        assert!(super::parse("h").is_err());
+        assert!(super::parse("").is_err());
+        assert_eq!(
+            Some(t),
+            super::parse_prefix("2024-01-02 03:04:05+01:00 error: x")
+        );
+        assert_eq!(super::FORMAT_LEN, super::format(super::now()).len());
+        assert_eq!(None, super::parse_prefix("error: x"));
+    }
+}
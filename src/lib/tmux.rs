@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Error, Result};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Terminal {
     session: String,
     window_id: usize,
@@ -17,6 +17,7 @@ impl std::fmt::Display for Terminal {
 
 #[derive(Debug)]
 pub struct PaneInfo {
+    pub window_index: usize,
     pub window_id: usize,
     pub window_name: String,
     pub tty: PathBuf,
@@ -29,7 +30,7 @@ impl std::str::FromStr for PaneInfo {
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let fields: Vec<&str> = s.split_whitespace().collect();
         match &fields[..] {
-            [window_id, window_name, tty, pane_id] => {
+            [window_index, window_id, window_name, tty, pane_id] => {
                 let window_id = window_id
                     .strip_prefix('@')
                     .ok_or_else(|| {
@@ -49,6 +50,7 @@ impl std::str::FromStr for PaneInfo {
                     })?
                     .parse()?;
                 let pane_info = PaneInfo {
+                    window_index: window_index.parse()?,
                     pane_id,
                     window_id,
                     window_name: window_name.to_string(),
@@ -63,7 +65,6 @@ impl std::str::FromStr for PaneInfo {
 
 #[derive(Debug)]
 pub struct Tmux {
-    cur_window: usize,
     sock: String,
     session: String,
 }
@@ -71,7 +72,6 @@ pub struct Tmux {
 impl Tmux {
     pub fn new(sock: &str, session: &str) -> Self {
         Self {
-            cur_window: 0,
             sock: sock.to_owned(),
             session: session.to_owned(),
         }
@@ -94,21 +94,39 @@ impl Tmux {
     }
 
     pub fn new_terminal(
-        &mut self,
+        &self,
         working_directory: &Path,
         name: &str,
     ) -> Result<Terminal> {
-        self.new_window(working_directory, name)?;
-        self.cur_window += 1;
+        let window = self.new_window(working_directory, name)?;
         let term = Terminal {
             session: self.session.clone(),
-            window_id: self.cur_window,
+            window_id: window,
             pane_id: 0,
         };
         tracing::debug!("Allocated terminal: {:?}", term);
         Ok(term)
     }
 
+    /// Looks up the terminal of the window with the given name.
+    pub fn find_terminal(&self, name: &str) -> Result<Option<Terminal>> {
+        let term = self
+            .list_panes()?
+            .into_iter()
+            .find(|pane| pane.window_name == name)
+            .map(|pane| Terminal {
+                session: self.session.clone(),
+                window_id: pane.window_index,
+                pane_id: 0,
+            });
+        Ok(term)
+    }
+
+    pub fn kill_window(&self, term: &Terminal) -> Result<()> {
+        let target = format!("{}:{}", term.session, term.window_id);
+        self.run(&["kill-window", "-t", &target])
+    }
+
     pub fn list_panes(&self) -> Result<Vec<PaneInfo>> {
         let out = self.exec(&[
             "list-panes",
@@ -116,7 +134,7 @@ impl Tmux {
             "-t",
             &self.session,
             "-F",
-            "#{window_id} #{window_name} #{pane_tty} #{pane_id}",
+            "#{window_index} #{window_id} #{window_name} #{pane_tty} #{pane_id}",
         ])?;
         let mut panes = Vec::new();
         for line in out.lines() {
@@ -150,6 +168,14 @@ impl Tmux {
         self.run(&["attach", "-t", &self.session])
     }
 
+    /// Command attaching to the window, to be executed in a new terminal.
+    pub fn attach_window_cmd(&self, name: &str) -> Vec<String> {
+        let target = format!("{}:{}", self.session, name);
+        ["tmux", "-L", &self.sock, "attach", "-t", &target]
+            .map(String::from)
+            .to_vec()
+    }
+
     /// For safety, launch commands in 2 steps:
     /// 1. send_text (key lookup disabled);
     /// 2. send_enter.
@@ -173,15 +199,22 @@ impl Tmux {
         self.run(&["send-keys", "-t", &term.to_string(), "^C"])
     }
 
+    /// Returns the index of the new window.
     #[rustfmt::skip] // I want each option-value pair on the same line.
-    fn new_window(&self, working_directory: &Path, name: &str) -> Result<()> {
+    fn new_window(&self, working_directory: &Path, name: &str) -> Result<usize> {
         let working_directory = working_directory.to_string_lossy();
-        self.run(&[
+        let out = self.exec(&[
             "new-window",
             "-c", &working_directory,
             "-n", name,
             "-t", &self.session,
-        ])
+            "-P", // Print info about the new window,
+            "-F", "#{window_index}", // formatted as just its index.
+        ])?;
+        let index = out.trim().parse().map_err(|e| {
+            anyhow!("Invalid window index: {:?}. Error: {:?}", out, e)
+        })?;
+        Ok(index)
     }
 
     fn rename_window(&self, window: usize, name: &str) -> Result<()> {
@@ -1,16 +1,25 @@
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
 use expanduser::expanduser;
 
-use pistactl::{cfg::Cfg, cmd, logger, tmux::Tmux};
+use pistactl::{
+    alert,
+    cfg::{self, Cfg},
+    cmd, logger, logs, notify, sandbox,
+    state::State,
+    timestamp,
+    tmux::Tmux,
+};
 
 #[derive(Parser, Debug)]
 pub struct Cli {
-    /// Path to configuration file
-    #[clap(short, long, default_value = concat!("~/.", pistactl::NAME!(), ".toml"))]
-    config: String,
+    /// Path to configuration file. If not given, it is looked for in
+    /// $XDG_CONFIG_HOME/pistactl/config.toml, or ~/.pistactl.toml,
+    /// and is layered on top of /etc/xdg/pistactl/config.toml
+    #[clap(short, long)]
+    config: Option<String>,
 
     /// Increase logging verbosity
     #[clap(short, long, default_value_t = false)]
@@ -28,14 +37,57 @@ pub struct Cli {
     #[clap(long)]
     dir: Option<PathBuf>,
 
+    /// Name of config profile to apply
+    #[clap(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Override a config value, like pista.interval=2, or slots.<name>.ttl=5.
+    /// Applied after PISTACTL_<KEY> environment variables, like
+    /// PISTACTL_PISTA__INTERVAL=2
+    #[clap(long = "set", value_name = "KEY=VALUE", global = true)]
+    set: Vec<String>,
+
     #[clap(subcommand)]
     pub command: Cmd,
 }
 
 impl Cli {
+    /// In merge order.
+    pub fn cfg_files(&self) -> Result<Vec<PathBuf>> {
+        match self.config {
+            Some(ref path) => Ok(vec![expanduser(path)?]),
+            None => cfg::paths::discover(),
+        }
+    }
+
     pub fn to_cfg(&self) -> Result<Cfg> {
-        let cfg_file = expanduser(&self.config)?;
-        let mut cfg = Cfg::from_file(&cfg_file)?;
+        self.to_cfg_with_profile(self.profile.as_deref())
+    }
+
+    /// For changes to the running session: unless given, the profile is
+    /// the one it was started or last switched with.
+    pub fn to_session_cfg(&self) -> Result<Cfg> {
+        let cfg = self.to_cfg()?;
+        if self.profile.is_some() || !cmd::is_started(&cfg) {
+            return Ok(cfg);
+        }
+        match State::read(&cfg.slots_fifos_dir)?.profile {
+            Some(profile) if cfg.profile.as_ref() != Some(&profile) => {
+                self.to_cfg_with_profile(Some(&profile))
+            }
+            _ => Ok(cfg),
+        }
+    }
+
+    fn to_cfg_with_profile(&self, profile: Option<&str>) -> Result<Cfg> {
+        let cfg_files = self.cfg_files()?;
+        let mut overrides = cfg::overrides::from_env();
+        for set in &self.set {
+            overrides.push(cfg::overrides::parse(set)?);
+        }
+        let mut cfg = logger::scoped(self.debug, || {
+            Cfg::from_files(&cfg_files, profile, &overrides)
+        })??;
         if self.debug {
             cfg.debug = true;
         }
@@ -48,30 +100,463 @@ impl Cli {
         if let Some(ref dir) = self.dir {
             cfg.slots_fifos_dir = dir.clone();
         }
+        cfg.expand_vars()?;
         Ok(cfg)
     }
 }
 
 #[derive(clap::Subcommand, Debug)]
 pub enum Cmd {
+    /// Validate the config and print the slots as they will be started
+    Check,
     Status,
     Start,
     Stop,
     Restart,
     Attach,
+
+    /// Set enabled = false for the slot in the config file,
+    /// and stop it, if the session is running
+    Disable {
+        slot: String,
+    },
+
+    /// Remove enabled = false from the slot in the config file,
+    /// and start it, if the session is running
+    Enable {
+        slot: String,
+    },
+
+    /// Apply config changes to the running session,
+    /// restarting only the slots which changed
+    Reload,
+
+    Profile {
+        #[clap(subcommand)]
+        command: ProfileCmd,
+    },
+
+    /// Change the config file, preserving its comments and formatting,
+    /// backing-up the previous version next to it. Or describe its format.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCmd,
+    },
+
+    /// Print the notifications emitted, including the ones dropped
+    /// by the rate limits, the oldest first
+    Notifications {
+        /// Only the ones since, in RFC 3339, or a duration ago, like 30m
+        #[clap(long, value_parser = timestamp::parse)]
+        since: Option<time::OffsetDateTime>,
+
+        /// Only the ones of the slot
+        #[clap(long)]
+        slot: Option<String>,
+    },
+
+    /// Print the err log of a slot, or pista, including its rotated files,
+    /// from the oldest
+    Logs {
+        /// Of the slot, or pista
+        name: String,
+
+        /// Print pista's out log instead
+        #[clap(long)]
+        out: bool,
+
+        /// Only the current file, not the rotated ones
+        #[clap(long)]
+        current: bool,
+
+        /// Only the lines since, in RFC 3339, or a duration ago, like 30m.
+        /// Needs logs.timestamps
+        #[clap(long, value_parser = timestamp::parse)]
+        since: Option<time::OffsetDateTime>,
+
+        /// Only the lines until, like since
+        #[clap(long, value_parser = timestamp::parse)]
+        until: Option<time::OffsetDateTime>,
+    },
+
+    /// Internal. Run a slot's cmd restricted by Landlock.
+    #[clap(hide = true)]
+    Sandbox {
+        #[clap(long)]
+        read: Vec<PathBuf>,
+
+        #[clap(long)]
+        write: Vec<PathBuf>,
+
+        #[clap(last = true, required = true)]
+        cmd: Vec<String>,
+    },
+
+    /// Internal. Send a notification of an event in the session,
+    /// as configured in the given snapshot of the notifications config.
+    #[clap(hide = true)]
+    Notify {
+        #[clap(long)]
+        settings: PathBuf,
+
+        #[clap(long, value_enum)]
+        event: notify::Event,
+
+        #[clap(long)]
+        slot: Option<String>,
+
+        #[clap(long)]
+        code: Option<i32>,
+
+        /// Log file, whose last lines are appended to the body.
+        #[clap(long)]
+        log: Option<PathBuf>,
+
+        body: String,
+    },
+
+    /// Internal. Wait for an action to be invoked on the notification of
+    /// the window, whose ID is read from stdin, and execute it.
+    #[clap(hide = true)]
+    WaitAction {
+        #[clap(long)]
+        settings: PathBuf,
+
+        #[clap(long)]
+        window: String,
+    },
+
+    /// Internal. Append stdin to the log file, rotating it, if given the
+    /// limits, and notifying of the lines matching the alert rules of
+    /// a slot, if given them.
+    #[clap(hide = true)]
+    WriteLog {
+        #[clap(long)]
+        file: PathBuf,
+
+        #[clap(long)]
+        max_size: Option<u64>,
+
+        #[clap(long)]
+        max_age: Option<u64>,
+
+        #[clap(long, default_value_t = 0)]
+        keep: usize,
+
+        #[clap(long)]
+        timestamps: bool,
+
+        #[clap(long, requires_all = ["settings", "slot"])]
+        rules: Option<PathBuf>,
+
+        #[clap(long)]
+        settings: Option<PathBuf>,
+
+        #[clap(long)]
+        slot: Option<String>,
+    },
+
+    /// Internal. Pass stdin on to the FIFO, notifying of the numbers
+    /// crossing the thresholds of the value alert rules of a slot.
+    #[clap(hide = true)]
+    WatchOut {
+        #[clap(long)]
+        settings: PathBuf,
+
+        #[clap(long)]
+        slot: String,
+
+        #[clap(long)]
+        rules: PathBuf,
+
+        #[clap(long)]
+        fifo: PathBuf,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ProfileCmd {
+    /// List profiles defined in the config, marking the active one
+    List,
+
+    /// Apply the profile to the running session,
+    /// restarting only the slots which it changes
+    Switch { name: String },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigCmd {
+    /// Add a slot at the end, or before another
+    AddSlot {
+        #[clap(long)]
+        name: String,
+
+        #[clap(long)]
+        ttl: i32,
+
+        #[clap(long)]
+        cmd: String,
+
+        #[clap(long)]
+        len: Option<usize>,
+
+        #[clap(long)]
+        before: Option<String>,
+    },
+
+    RemoveSlot {
+        name: String,
+    },
+
+    /// Move a slot before or after another, defined in the same file
+    MoveSlot {
+        name: String,
+
+        #[clap(
+            long,
+            required_unless_present = "after",
+            conflicts_with = "after"
+        )]
+        before: Option<String>,
+
+        #[clap(long)]
+        after: Option<String>,
+    },
+
+    /// Set a value at a dot-separated key, like pista.separator or
+    /// slots.<name>.ttl. The value is parsed as TOML,
+    /// or taken as a string, if it is not valid TOML.
+    Set {
+        key: String,
+        value: String,
+    },
+
+    /// Print changes which upgrade the config files to the current
+    /// version of the format
+    Migrate {
+        /// Make the changes, rather than just print them
+        #[clap(long)]
+        apply: bool,
+    },
+
+    /// Print the config files merged with their includes,
+    /// or, with --effective, the config resulting from them
+    Show {
+        /// Resolve the profile, overrides, slot templates and defaults
+        #[clap(long)]
+        effective: bool,
+    },
+
+    /// Print the config files looked for, and the ones in effect,
+    /// including their includes, and which one provides each key
+    Paths,
+
+    /// Print JSON Schema of the config file format,
+    /// for editors to validate and complete it
+    Schema,
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let cfg = cli.to_cfg()?;
+    match &cli.command {
+        // Internal commands are executed from slot scripts,
+        // so they take all they need as arguments, not from the config.
+        Cmd::Sandbox { read, write, cmd } => {
+            logger::init(cli.debug)?;
+            sandbox::exec(read, write, cmd)
+        }
+        Cmd::Notify {
+            settings,
+            event,
+            slot,
+            code,
+            log,
+            body,
+        } => {
+            logger::init(cli.debug)?;
+            notify::send(
+                settings,
+                *event,
+                slot.clone(),
+                *code,
+                body.clone(),
+                log.as_deref(),
+            )
+        }
+        Cmd::WaitAction { settings, window } => {
+            logger::init(cli.debug)?;
+            notify::wait_action(settings, window)
+        }
+        Cmd::WriteLog {
+            file,
+            max_size,
+            max_age,
+            keep,
+            timestamps,
+            rules,
+            settings,
+            slot,
+        } => {
+            logger::init(cli.debug)?;
+            let limits = cfg::Logs {
+                max_size: *max_size,
+                max_age: *max_age,
+                keep: *keep,
+                timestamps: *timestamps,
+            };
+            let mut alerts = match (rules, settings, slot) {
+                (Some(rules), Some(settings), Some(slot)) => {
+                    Some(alert::ErrAlerts::new(settings, slot, rules)?)
+                }
+                _ => None,
+            };
+            logs::write(file, &limits, |line| {
+                if let Some(alerts) = alerts.as_mut() {
+                    alerts.check(line);
+                }
+            })
+        }
+        Cmd::WatchOut {
+            settings,
+            slot,
+            rules,
+            fifo,
+        } => {
+            logger::init(cli.debug)?;
+            alert::watch_out(settings, slot, rules, fifo)
+        }
+        // Config changes do not need a valid config to start with,
+        // since they may be what makes it valid.
+        Cmd::Config { command } => {
+            logger::init(cli.debug)?;
+            config(&cli, command)
+        }
+        Cmd::Check => cmd::check(&init(cli.to_cfg()?)?),
+        Cmd::Status => {
+            let cfg = init(cli.to_cfg()?)?;
+            cmd::status(&cfg, &tmux(&cfg))
+        }
+        Cmd::Attach => cmd::attach(&tmux(&init(cli.to_cfg()?)?)),
+        Cmd::Start => {
+            let cfg = init(cli.to_cfg()?)?;
+            cmd::start(&cfg, &tmux(&cfg))
+        }
+        Cmd::Stop => {
+            let cfg = init(cli.to_cfg()?)?;
+            cmd::stop(&cfg, &tmux(&cfg))
+        }
+        Cmd::Restart => {
+            let cfg = init(cli.to_cfg()?)?;
+            cmd::restart(&cfg, &tmux(&cfg))
+        }
+        Cmd::Reload => {
+            let cfg = init(cli.to_session_cfg()?)?;
+            cmd::reload(&cfg, &tmux(&cfg))
+        }
+        Cmd::Logs {
+            name,
+            out,
+            current,
+            since,
+            until,
+        } => {
+            let cfg = init(cli.to_cfg()?)?;
+            cmd::logs(&cfg, name, *out, *current, *since, *until)
+        }
+        Cmd::Notifications { since, slot } => {
+            let cfg = init(cli.to_cfg()?)?;
+            cmd::notifications(&cfg, *since, slot.as_deref())
+        }
+        Cmd::Disable { slot } => set_enabled(&cli, slot, false),
+        Cmd::Enable { slot } => set_enabled(&cli, slot, true),
+        Cmd::Profile {
+            command: ProfileCmd::List,
+        } => cmd::profiles(&init(cli.to_cfg()?)?),
+        Cmd::Profile {
+            command: ProfileCmd::Switch { name },
+        } => {
+            let cfg = init(cli.to_cfg_with_profile(Some(name))?)?;
+            cmd::reload(&cfg, &tmux(&cfg))
+        }
+    }
+}
+
+/// Initializes logging, as the config says.
+fn init(cfg: Cfg) -> Result<Cfg> {
     logger::init(cfg.debug)?;
     tracing::debug!("cfg: {:#?}", &cfg);
-    let mut tmux = Tmux::new(&cfg.sock, &cfg.session);
-    match &cli.command {
-        Cmd::Status => cmd::status(&cfg, &tmux),
-        Cmd::Attach => cmd::attach(&tmux),
-        Cmd::Start => cmd::start(&cfg, &mut tmux),
-        Cmd::Stop => cmd::stop(&cfg, &tmux),
-        Cmd::Restart => cmd::restart(&cfg, &mut tmux),
+    Ok(cfg)
+}
+
+fn tmux(cfg: &Cfg) -> Tmux {
+    Tmux::new(&cfg.sock, &cfg.session)
+}
+
+fn set_enabled(cli: &Cli, slot: &str, enabled: bool) -> Result<()> {
+    let cfg = init(cli.to_cfg()?)?;
+    cfg::edit::set_slot_enabled(&cli.cfg_files()?, slot, enabled)?;
+    if cmd::is_started(&cfg) {
+        // Re-read, to pick-up the change.
+        let cfg = cli.to_session_cfg()?;
+        cmd::reload(&cfg, &tmux(&cfg))?;
+    }
+    Ok(())
+}
+
+fn config(cli: &Cli, command: &ConfigCmd) -> Result<()> {
+    use cfg::edit;
+
+    let cfg_files = || cli.cfg_files();
+    match command {
+        ConfigCmd::AddSlot {
+            name,
+            ttl,
+            cmd,
+            len,
+            before,
+        } => {
+            let mut slot = vec![("name", name.as_str().into())];
+            if let Some(len) = len {
+                slot.push(("len", (*len as i64).into()));
+            }
+            slot.push(("ttl", (*ttl as i64).into()));
+            slot.push(("cmd", cmd.as_str().into()));
+            edit::add_slot(&cfg_files()?, &slot, before.as_deref())
+        }
+        ConfigCmd::RemoveSlot { name } => {
+            edit::remove_slot(&cfg_files()?, name)
+        }
+        ConfigCmd::MoveSlot {
+            name,
+            before,
+            after,
+        } => {
+            let anchor = match (before, after) {
+                (Some(other), None) => edit::Anchor::Before(other),
+                (None, Some(other)) => edit::Anchor::After(other),
+                _ => bail!("Exactly one of --before and --after is needed"),
+            };
+            edit::move_slot(&cfg_files()?, name, anchor)
+        }
+        ConfigCmd::Set { key, value } => edit::set(&cfg_files()?, key, value),
+        ConfigCmd::Migrate { apply } => {
+            cmd::config_migrate(&cfg_files()?, *apply)
+        }
+        ConfigCmd::Show { effective: false } => {
+            let merged = cfg::merged(&cfg_files()?)?;
+            print!("{}", toml::to_string_pretty(&merged)?);
+            Ok(())
+        }
+        ConfigCmd::Show { effective: true } => {
+            print!("{}", cli.to_cfg()?.to_toml()?);
+            Ok(())
+        }
+        ConfigCmd::Paths => {
+            cmd::config_paths(&cfg_files()?, cli.config.is_none())
+        }
+        ConfigCmd::Schema => {
+            println!("{}", serde_json::to_string_pretty(&cfg::schema())?);
+            Ok(())
+        }
     }
 }